@@ -48,8 +48,8 @@ impl AABB{
             // Treat the two points a and b as extrema for the bounding box, so we don't require a
             // particular minimum/maximum coordinate order.
             x : Interval::new_arg(a.e[0].min(b.e[0]), a.e[0].max(b.e[0])),
-            y : Interval::new_arg(a.e[1].min(b.e[1]), a.e[0].max(b.e[1])),
-            z : Interval::new_arg(a.e[2].min(b.e[2]), a.e[0].max(b.e[2])),
+            y : Interval::new_arg(a.e[1].min(b.e[1]), a.e[1].max(b.e[1])),
+            z : Interval::new_arg(a.e[2].min(b.e[2]), a.e[2].max(b.e[2])),
         }
     }
 
@@ -59,6 +59,24 @@ impl AABB{
         self.x
     }
 
+    pub fn longest_axis(&self) -> i32{
+        // Returns the index of the longest axis of the bounding box.
+        if self.x.size() > self.y.size() {
+            if self.x.size() > self.z.size() { 0 } else { 2 }
+        } else if self.y.size() > self.z.size() { 1 } else { 2 }
+    }
+
+    pub fn centroid(&self) -> Point3{
+        Point3::new_arg(0.5 * (self.x.min + self.x.max), 0.5 * (self.y.min + self.y.max), 0.5 * (self.z.min + self.z.max))
+    }
+
+    pub fn surface_area(&self) -> f64{
+        let dx = self.x.size();
+        let dy = self.y.size();
+        let dz = self.z.size();
+        2.0 * (dx*dy + dy*dz + dz*dx)
+    }
+
     pub fn hit(&self, r : &mut Ray, mut ray_t : Interval) -> bool{
         for a in 0..3{
             // t_0 and t_1 rely on 1/ b_x (or y/z), so this takes care of this annoying case by simply registering a hit
@@ -88,6 +106,28 @@ impl AABB{
         true
     }
 
+    pub fn hit_inv(&self, orig : &Point3, inv_dir : &Rvec3, mut ray_t : Interval) -> bool{
+        // Slab test for callers that test many boxes against one ray and have already
+        // computed the reciprocal of its direction.
+        for a in 0..3{
+            let mut t0 = (self.axis(a as i32).min - orig.e[a]) * inv_dir.e[a];
+            let mut t1 = (self.axis(a as i32).max - orig.e[a]) * inv_dir.e[a];
+
+            if inv_dir.e[a] < 0.0{
+                std::mem::swap(&mut t0, &mut t1);
+            }
+
+            if t0 > ray_t.min { ray_t.min = t0;}
+            if t1 < ray_t.max { ray_t.max = t1;}
+
+            if ray_t.max <= ray_t.min{
+                return false;
+            }
+        }
+
+        true
+    }
+
     pub fn pad(&self) -> AABB{
         // Return an AABB that has no side narrower than some delta, padding if necessary.
         let delta = 0.0001;
@@ -0,0 +1,210 @@
+// Arbitrary output variables: extra passes recorded next to the image for compositing. Most of
+// them describe the first surface each camera sample hits, looking through participating media,
+// and are averaged over the samples of a pixel, like the image; IDs can't be averaged, so the ID
+// passes keep the first sample's.
+
+use crate::color::*;
+use crate::film::*;
+use crate::hit::HitRecord;
+use crate::rvec3::*;
+use crate::utility::splitmix64;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AovType{
+    Albedo, // Reflectance of the first surface; lights and the background show their color
+    Normal, // World-space shading normal, facing the camera
+    Depth, // Distance along the view direction
+    Position, // World-space position
+    Uv, // Texture coordinates
+    ObjectId, // Top-level object of the scene, counting from 1
+    MaterialId, // Material, numbered 1, 2, ... in the order they show up down the image
+    Direct, // Light that reached the camera after at most one bounce, emitters and background included
+    Indirect, // The rest of the image
+}
+
+impl AovType{
+    pub const ALL : [AovType; 9] = [
+        AovType::Albedo, AovType::Normal, AovType::Depth, AovType::Position, AovType::Uv,
+        AovType::ObjectId, AovType::MaterialId, AovType::Direct, AovType::Indirect,
+    ];
+
+    pub fn from_name(name : &str) -> Option<AovType>{
+        AovType::ALL.into_iter().find(|aov| aov.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str{
+        match self {
+            AovType::Albedo => "albedo",
+            AovType::Normal => "normal",
+            AovType::Depth => "depth",
+            AovType::Position => "position",
+            AovType::Uv => "uv",
+            AovType::ObjectId => "object",
+            AovType::MaterialId => "material",
+            AovType::Direct => "direct",
+            AovType::Indirect => "indirect",
+        }
+    }
+
+    pub fn channels(&self) -> &'static [&'static str]{
+        // Channel names in EXR layers, taken from the components of the pass's pixels in order.
+        match self {
+            AovType::Albedo | AovType::Direct | AovType::Indirect => &["R", "G", "B"],
+            AovType::Normal | AovType::Position => &["X", "Y", "Z"],
+            AovType::Depth => &["Z"],
+            AovType::Uv => &["U", "V"],
+            AovType::ObjectId | AovType::MaterialId => &["id"],
+        }
+    }
+
+    pub fn is_id(&self) -> bool{
+        matches!(self, AovType::ObjectId | AovType::MaterialId)
+    }
+}
+
+// What one camera sample found at its first hit, filled in by the integrator. Everything stays
+// zero when the sample hits nothing.
+pub struct AovSample{
+    pub albedo : Color,
+    pub normal : Rvec3,
+    pub depth : f64,
+    pub position : Point3,
+    pub u : f64,
+    pub v : f64,
+    pub object_id : u32,
+    pub material : usize, // Address of the material, numbered by number_materials after the render
+    pub direct : Color, // Part of the sample's radiance that is direct light
+}
+
+impl Default for AovSample{
+    fn default() -> Self{
+        Self::new()
+    }
+}
+
+impl AovSample{
+    pub fn new() -> Self{
+        Self {
+            albedo : Color::new(),
+            normal : Rvec3::new(),
+            depth : 0.0,
+            position : Point3::new(),
+            u : 0.0,
+            v : 0.0,
+            object_id : 0,
+            material : 0,
+            direct : Color::new(),
+        }
+    }
+
+    pub fn record_hit(&mut self, rec : &HitRecord, depth : f64){
+        self.normal = rec.normal;
+        self.depth = depth;
+        self.position = rec.p;
+        self.u = rec.u;
+        self.v = rec.v;
+        self.object_id = rec.object_id;
+        self.material = Arc::as_ptr(&rec.mat) as *const () as usize;
+    }
+
+    pub fn record_light(&mut self, emitted : Color){
+        // Lights and the background reflect nothing; like denoisers expect, their albedo is the
+        // color they show, clamped to 1.
+        self.albedo = Color::new_arg(emitted[0].min(1.0), emitted[1].min(1.0), emitted[2].min(1.0));
+    }
+
+    fn value(&self, aov : AovType, color : Color) -> Color{
+        match aov {
+            AovType::Albedo => self.albedo,
+            AovType::Normal => self.normal,
+            AovType::Depth => Color::new_arg(self.depth, self.depth, self.depth),
+            AovType::Position => self.position,
+            AovType::Uv => Color::new_arg(self.u, self.v, 0.0),
+            AovType::ObjectId => Color::new_arg(self.object_id as f64, self.object_id as f64, self.object_id as f64),
+            AovType::MaterialId => Color::new_arg(self.material as f64, self.material as f64, self.material as f64),
+            AovType::Direct => self.direct,
+            AovType::Indirect => color - self.direct,
+        }
+    }
+}
+
+pub fn record_aovs(film : &mut Film, i : i32, j : i32, sample : &AovSample, color : Color){
+    // Add a sample of pixel i,j, whose radiance was `color`, to every pass the film has.
+    for (aov, layer) in film.layers_mut() {
+        if aov.is_id() && layer.sample_count(i, j) > 0 {
+            continue;
+        }
+        layer.add_sample(i, j, sample.value(*aov, color));
+    }
+}
+
+pub fn number_materials(film : &mut Film){
+    // Replace the material addresses by 1, 2, ... in the order the materials first appear, row
+    // by row, so the IDs don't change from one run to the next.
+    let Some(layer) = film.layer_mut(AovType::MaterialId) else { return };
+    let mut ids : HashMap<u64, usize> = HashMap::new();
+    for j in 0..layer.height() {
+        for i in 0..layer.width() {
+            if layer.sample_count(i, j) == 0 {
+                continue;
+            }
+            let key = layer.pixel(i, j)[0];
+            if key == 0.0 {
+                continue;
+            }
+            let next = ids.len() + 1;
+            let id = *ids.entry(key.to_bits()).or_insert(next);
+            layer.set_pixel(i, j, Color::new_arg(id as f64, id as f64, id as f64));
+        }
+    }
+}
+
+pub fn preview(aov : AovType, layer : &Film) -> Film{
+    // Version of a pass that reads as an 8-bit image: normals mapped to [0, 1], depth and
+    // position scaled to the range they cover (near is dark) and IDs given distinct colors.
+    let (width, height) = (layer.width(), layer.height());
+    let mut low = Color::new_arg(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+    let mut high = -low;
+    for j in 0..height {
+        for i in 0..width {
+            if layer.sample_count(i, j) > 0 {
+                let c = layer.pixel(i, j);
+                for k in 0..3 {
+                    low[k] = low[k].min(c[k]);
+                    high[k] = high[k].max(c[k]);
+                }
+            }
+        }
+    }
+
+    let mut shown_film = Film::new(width, height);
+    for j in 0..height {
+        for i in 0..width {
+            let c = layer.pixel(i, j);
+            let shown = match aov {
+                AovType::Normal => 0.5 * (c + Color::new_arg(1.0, 1.0, 1.0)),
+                AovType::Depth | AovType::Position => {
+                    let scale = |k : usize| (c[k] - low[k]) / (high[k] - low[k]).max(f64::MIN_POSITIVE);
+                    Color::new_arg(scale(0), scale(1), scale(2))
+                }
+                AovType::ObjectId | AovType::MaterialId => id_color(c[0]),
+                _ => c,
+            };
+            shown_film.add_sample(i, j, shown);
+        }
+    }
+    shown_film
+}
+
+fn id_color(id : f64) -> Color{
+    // A bright color picked by hashing the ID, black for none.
+    if id == 0.0 {
+        return Color::new();
+    }
+    let h = splitmix64(id as u64);
+    let channel = |shift : u32| 0.2 + 0.8 * ((h >> shift) & 0xff) as f64 / 255.0;
+    Color::new_arg(channel(0), channel(8), channel(16))
+}
@@ -4,103 +4,428 @@ use crate::aabb::AABB;
 
 use crate::utility::*;
 use std::cmp::Ordering;
-use std::rc::Rc;
-use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
 use crate::interval::*;
+use crate::rvec3::*;
 
-pub struct BvhNode{
-    bbox : AABB,    
-    _left : Rc<RefCell<dyn Hittable>>,
-    _right : Rc<RefCell<dyn Hittable>>
+// How a BVH build chooses where to split each node.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SplitMethod{
+    Median, // Split at the object-count median along the longest axis; fastest to build
+    Sah,    // Binned surface area heuristic; slower to build, cheaper to trace
 }
 
+impl SplitMethod{
+    pub fn from_name(name : &str) -> Option<SplitMethod>{
+        match name {
+            "median" => Some(SplitMethod::Median),
+            "sah" => Some(SplitMethod::Sah),
+            _ => None,
+        }
+    }
+}
 
-impl BvhNode {
-    pub fn new(objects : &mut Vec<Rc<RefCell<dyn Hittable>>>, start : i32, end : i32) -> Self{
-        //let mut objects = src_objects.clone(); // Create a modifiable array of the source scene objects
+// Shape of a finished BVH, filled in while it is built.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct BvhStats{
+    pub primitives : usize,
+    pub interior_nodes : usize, // Nodes whose children are other nodes
+    pub leaf_nodes : usize,     // Nodes whose children are primitives
+    pub depth : usize,          // Depth of the deepest node, the root being 1
+}
 
-        let axis = random_int(0,2);
+impl BvhStats{
+    pub fn nodes(&self) -> usize{
+        self.interior_nodes + self.leaf_nodes
+    }
+}
 
+impl fmt::Display for BvhStats{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "BVH: {} primitives, {} nodes ({} interior, {} leaves), depth {}",
+            self.primitives, self.nodes(), self.interior_nodes, self.leaf_nodes, self.depth)
+    }
+}
 
-        type FunType = fn(&Rc<RefCell<(dyn Hittable + 'static)>>, &Rc<RefCell<(dyn Hittable + 'static)>>) -> Ordering;
-        let mut comparator : FunType = BvhNode::box_x_compare;
-        if axis == 1 { comparator = BvhNode::box_y_compare}
-        else if axis == 2 { comparator = BvhNode::box_z_compare;}
+pub struct BvhNode{
+    bbox : AABB,
+    _left : Arc<dyn Hittable>,
+    _right : Arc<dyn Hittable>
+}
 
 
-        let lft : Rc<RefCell<dyn Hittable>>;
-        let rght : Rc<RefCell<dyn Hittable>>;
+impl BvhNode {
+    // Number of buckets the centroids are sorted into when evaluating SAH split candidates.
+    const SAH_BINS : usize = 16;
 
-        let object_span = end - start;
+    pub fn new(objects : &mut [Arc<dyn Hittable>], method : SplitMethod, depth : usize, stats : &mut BvhStats) -> Self{
+        stats.depth = stats.depth.max(depth);
+
+        let lft : Arc<dyn Hittable>;
+        let rght : Arc<dyn Hittable>;
+
+        let object_span = objects.len();
         if object_span == 1{
-            lft = Rc::clone(&objects[start as usize]);
-            rght = Rc::clone(&objects[start as usize]);
+            stats.leaf_nodes += 1;
+            lft = Arc::clone(&objects[0]);
+            rght = Arc::clone(&objects[0]);
         }else if object_span == 2{
-            if comparator(&Rc::clone(&objects[start as usize]), &Rc::clone(&objects[(start+1) as usize])) == Ordering::Less{
-                lft = Rc::clone(&objects[start as usize]);
-                rght = Rc::clone(&objects[(start+1) as usize]);
-            }else{
-                lft = Rc::clone(&objects[(start+1) as usize]);    
-                rght = Rc::clone(&objects[start as usize]);
-            }
+            stats.leaf_nodes += 1;
+            lft = Arc::clone(&objects[0]);
+            rght = Arc::clone(&objects[1]);
         }else{
-            objects.sort_by(comparator);
-        
-            let mid = start + object_span/2;
-            lft = Rc::new(RefCell::new(BvhNode::new(objects,start,mid)));
-            rght = Rc::new(RefCell::new(BvhNode::new(objects,mid,end))); 
+            stats.interior_nodes += 1;
+            let (mid, _) = BvhNode::split(objects, method);
+
+            let (left_objects, right_objects) = objects.split_at_mut(mid);
+            lft = Arc::new(BvhNode::new(left_objects, method, depth + 1, stats));
+            rght = Arc::new(BvhNode::new(right_objects, method, depth + 1, stats));
         }
 
-        let bbbox = AABB::new_boxes(lft.as_ref().borrow().bounding_box(), rght.as_ref().borrow().bounding_box());
-          
+        let bbbox = AABB::new_boxes(lft.bounding_box(), rght.bounding_box());
+
         Self { bbox: bbbox, _left: lft, _right: rght }
     }
 
-    pub fn new_list(mut list : HittableList) -> Self{
-        let ln = list.objects.len() as i32;
-        BvhNode::new(&mut list.objects, 0, ln)
+    pub fn new_list(list : HittableList) -> Self{
+        BvhNode::new_list_method(list, SplitMethod::Sah).0
+    }
+
+    pub fn new_list_method(mut list : HittableList, method : SplitMethod) -> (Self, BvhStats){
+        let mut stats = BvhStats{ primitives : list.objects.len(), ..Default::default() };
+        let node = BvhNode::new(&mut list.objects, method, 1, &mut stats);
+        (node, stats)
+    }
+
+    fn split(objects : &mut [Arc<dyn Hittable>], method : SplitMethod) -> (usize, i32){
+        // Reorder objects so the left child's share comes first; returns where the right
+        // child's share starts and the axis the split was made along.
+        match method {
+            SplitMethod::Median => BvhNode::split_median(objects),
+            SplitMethod::Sah => BvhNode::split_sah(objects),
+        }
+    }
+
+    fn split_median(objects : &mut [Arc<dyn Hittable>]) -> (usize, i32){
+        // Sort along the axis the centroids spread the most on and cut the slice in half.
+        let axis = BvhNode::centroid_bounds(objects).longest_axis();
+        objects.sort_by(|a, b| BvhNode::box_compare(a, b, axis));
+        (objects.len() / 2, axis)
     }
 
-    pub fn box_compare(a : &Rc<RefCell<dyn Hittable>>, b : &Rc<RefCell<dyn Hittable>>, axis_index : i32) -> Ordering{
-        if a.borrow_mut().bounding_box().axis(axis_index).min < b.borrow_mut().bounding_box().axis(axis_index).min{
+    fn split_sah(objects : &mut [Arc<dyn Hittable>]) -> (usize, i32){
+        // Bin the centroids along each axis and pick the bin boundary that minimises
+        // left_count * left_area + right_count * right_area.
+        let centroids = BvhNode::centroid_bounds(objects);
+
+        let mut best_cost = INFINITY;
+        let mut best_axis = 0;
+        let mut best_bin = 0;
+
+        for axis in 0..3{
+            let extent = centroids.axis(axis);
+            if extent.size() <= 0.0 {
+                continue;
+            }
+
+            let mut counts = [0usize; BvhNode::SAH_BINS];
+            let mut bounds = [AABB::new(); BvhNode::SAH_BINS];
+            for object in objects.iter(){
+                let bbox = object.bounding_box();
+                let bin = BvhNode::bin_index(bbox.centroid()[axis as usize], extent);
+                counts[bin] += 1;
+                bounds[bin] = AABB::new_boxes(bounds[bin], bbox);
+            }
+
+            // Sweep from the right so every boundary knows the area and count to its right.
+            let mut right_area = [0.0; BvhNode::SAH_BINS];
+            let mut right_count = [0usize; BvhNode::SAH_BINS];
+            let mut acc = AABB::new();
+            let mut n = 0;
+            for bin in (1..BvhNode::SAH_BINS).rev(){
+                acc = AABB::new_boxes(acc, bounds[bin]);
+                n += counts[bin];
+                right_count[bin] = n;
+                if n > 0 { right_area[bin] = acc.surface_area(); }
+            }
+
+            let mut acc = AABB::new();
+            let mut n = 0;
+            for bin in 0..BvhNode::SAH_BINS-1{
+                acc = AABB::new_boxes(acc, bounds[bin]);
+                n += counts[bin];
+                if n == 0 || right_count[bin+1] == 0 {
+                    continue;
+                }
+
+                let cost = (n as f64) * acc.surface_area() + (right_count[bin+1] as f64) * right_area[bin+1];
+                if cost < best_cost {
+                    best_cost = cost;
+                    best_axis = axis;
+                    best_bin = bin;
+                }
+            }
+        }
+
+        // Every centroid landed in the same spot, so no boundary separates anything.
+        if best_cost == INFINITY {
+            return BvhNode::split_median(objects);
+        }
+
+        let extent = centroids.axis(best_axis);
+        let mut mid = 0;
+        for i in 0..objects.len(){
+            if BvhNode::bin_index(objects[i].bounding_box().centroid()[best_axis as usize], extent) <= best_bin {
+                objects.swap(i, mid);
+                mid += 1;
+            }
+        }
+        (mid, best_axis)
+    }
+
+    fn bin_index(c : f64, extent : Interval) -> usize{
+        let bin = ((c - extent.min) / extent.size() * (BvhNode::SAH_BINS as f64)) as usize;
+        bin.min(BvhNode::SAH_BINS - 1)
+    }
+
+    fn centroid_bounds(objects : &[Arc<dyn Hittable>]) -> AABB{
+        let mut bounds = AABB::new();
+        for object in objects.iter(){
+            let c : Point3 = object.bounding_box().centroid();
+            bounds = AABB::new_boxes(bounds, AABB::new_points(c, c));
+        }
+        bounds
+    }
+
+    pub fn box_compare(a : &Arc<dyn Hittable>, b : &Arc<dyn Hittable>, axis_index : i32) -> Ordering{
+        if a.bounding_box().axis(axis_index).min < b.bounding_box().axis(axis_index).min{
             return Ordering::Less;
-        }else if a.borrow_mut().bounding_box().axis(axis_index).min == b.borrow_mut().bounding_box().axis(axis_index).min{
+        }else if a.bounding_box().axis(axis_index).min == b.bounding_box().axis(axis_index).min{
             return Ordering::Equal;
         }
         Ordering::Greater
     }
 
-    pub fn box_x_compare(a : &Rc<RefCell<dyn Hittable>>, b : &Rc<RefCell<dyn Hittable>>) -> Ordering{
+    pub fn box_x_compare(a : &Arc<dyn Hittable>, b : &Arc<dyn Hittable>) -> Ordering{
         BvhNode::box_compare(a, b, 0)
     }
 
 
-    pub fn box_y_compare(a : &Rc<RefCell<dyn Hittable>>, b  : &Rc<RefCell<dyn Hittable>>) -> Ordering{
+    pub fn box_y_compare(a : &Arc<dyn Hittable>, b  : &Arc<dyn Hittable>) -> Ordering{
         BvhNode::box_compare(a, b, 1)
     }
 
 
-    pub fn box_z_compare(a : &Rc<RefCell<dyn Hittable>>, b : &Rc<RefCell<dyn Hittable>>) -> Ordering{
+    pub fn box_z_compare(a : &Arc<dyn Hittable>, b : &Arc<dyn Hittable>) -> Ordering{
         BvhNode::box_compare(a, b, 2)
     }
 }
 
 impl Hittable for BvhNode{
-    fn hit(&mut self, ray: &mut crate::ray::Ray, ray_t : &mut crate::interval::Interval, rec: &mut HitRecord) -> bool {
+    fn hit(&self, ray: &mut crate::ray::Ray, ray_t : &mut crate::interval::Interval, rec: &mut HitRecord) -> bool {
         if !self.bbox.hit(ray,*ray_t) {
             return false;
         }
-        
-        let hit__left = self._left.borrow_mut().hit(ray,ray_t,rec);
+
+        let hit_left = self._left.hit(ray,ray_t,rec);
         let mut mx = ray_t.max;
-        if hit__left { mx = rec.t;}
-        
-        let hit__right = self._right.borrow_mut().hit(ray, &mut Interval::new_arg(ray_t.min, mx), rec);
-        
-        hit__left || hit__right
+        if hit_left { mx = rec.t;}
+
+        let hit_right = self._right.hit(ray, &mut Interval::new_arg(ray_t.min, mx), rec);
+
+        hit_left || hit_right
     }
 
     fn bounding_box(&self) -> AABB {
         self.bbox
-    }    
-}
\ No newline at end of file
+    }
+}
+
+// One entry of a LinearBvh. Interior nodes keep their first child right after themselves and
+// store the index of the second child; leaves store a range of the primitive array.
+#[derive(Clone, Copy)]
+struct LinearBvhNode{
+    bbox : AABB,
+    offset : u32, // Leaf: first primitive. Interior: index of the second child
+    count : u32,  // Number of primitives in a leaf, 0 for interior nodes
+    axis : u8,    // Axis the interior node was split along
+}
+
+pub struct LinearBvh{
+    nodes : Vec<LinearBvhNode>,
+    primitives : Vec<Arc<dyn Hittable>>,
+    ids : Vec<u32>, // Object IDs the list gave the primitives, in their new order
+    depth : usize, // Depth of the deepest node, which bounds the traversal stack
+}
+
+impl LinearBvh{
+    // Leaves hold at most this many primitives.
+    const LEAF_SIZE : usize = 2;
+    // Past this depth the build falls back to median splits, which halve the node each time, so
+    // SAH splits that keep peeling off a few primitives can't make the tree arbitrarily deep.
+    const MAX_SAH_DEPTH : usize = 40;
+    // Traversal keeps its stack in a fixed-size array when the tree is shallow enough, which is
+    // nearly always, and on the heap otherwise.
+    const STACK_SIZE : usize = 64;
+
+    pub fn new_list(list : HittableList) -> Self{
+        LinearBvh::new_list_method(list, SplitMethod::Sah).0
+    }
+
+    pub fn new_list_method(mut list : HittableList, method : SplitMethod) -> (Self, BvhStats){
+        let mut stats = BvhStats{ primitives : list.objects.len(), ..Default::default() };
+        let mut nodes = Vec::with_capacity(2 * list.objects.len());
+        // The build reorders the objects, so remember each one's ID by its address.
+        let ids_by_object : HashMap<*const (), u32> = list.objects.iter()
+            .zip(list.ids())
+            .map(|(object, &id)| (Arc::as_ptr(object) as *const (), id))
+            .collect();
+        if !list.objects.is_empty() {
+            LinearBvh::build(&mut list.objects, 0, method, 1, &mut nodes, &mut stats);
+        }
+        let ids = if ids_by_object.is_empty() {
+            Vec::new()
+        } else {
+            list.objects.iter().map(|object| ids_by_object.get(&(Arc::as_ptr(object) as *const ())).copied().unwrap_or(0)).collect()
+        };
+        (Self { nodes, primitives : list.objects, ids, depth : stats.depth }, stats)
+    }
+
+    fn build(objects : &mut [Arc<dyn Hittable>], first : usize, method : SplitMethod, depth : usize,
+             nodes : &mut Vec<LinearBvhNode>, stats : &mut BvhStats) -> usize{
+        stats.depth = stats.depth.max(depth);
+
+        let mut bbox = AABB::new();
+        for object in objects.iter(){
+            bbox = AABB::new_boxes(bbox, object.bounding_box());
+        }
+
+        let index = nodes.len();
+        if objects.len() <= LinearBvh::LEAF_SIZE {
+            stats.leaf_nodes += 1;
+            nodes.push(LinearBvhNode{ bbox, offset : first as u32, count : objects.len() as u32, axis : 0 });
+            return index;
+        }
+
+        stats.interior_nodes += 1;
+        let method = if depth > LinearBvh::MAX_SAH_DEPTH { SplitMethod::Median } else { method };
+        let (mid, axis) = BvhNode::split(objects, method);
+
+        // Reserve this node's slot now; the second child's index is only known once the first
+        // child's subtree has been laid out.
+        nodes.push(LinearBvhNode{ bbox, offset : 0, count : 0, axis : axis as u8 });
+        let (left_objects, right_objects) = objects.split_at_mut(mid);
+        LinearBvh::build(left_objects, first, method, depth + 1, nodes, stats);
+        let second = LinearBvh::build(right_objects, first + mid, method, depth + 1, nodes, stats);
+        nodes[index].offset = second as u32;
+
+        index
+    }
+}
+
+impl Hittable for LinearBvh{
+    fn hit(&self, ray: &mut crate::ray::Ray, ray_t : &mut Interval, rec: &mut HitRecord) -> bool {
+        if self.nodes.is_empty() {
+            return false;
+        }
+
+        let orig = ray.origin();
+        let dir = ray.direction();
+        let inv_dir = Rvec3::new_arg(1.0 / dir.e[0], 1.0 / dir.e[1], 1.0 / dir.e[2]);
+        let dir_is_neg = [inv_dir.e[0] < 0.0, inv_dir.e[1] < 0.0, inv_dir.e[2] < 0.0];
+
+        let mut hit_anything = false;
+        let mut closest_so_far = ray_t.max;
+
+        // Every interior node on the way down leaves at most one child on the stack.
+        let mut fixed_stack = [0usize; LinearBvh::STACK_SIZE];
+        let mut heap_stack;
+        let stack : &mut [usize] = if self.depth <= LinearBvh::STACK_SIZE {
+            &mut fixed_stack
+        } else {
+            heap_stack = vec![0usize; self.depth];
+            &mut heap_stack
+        };
+        let mut stack_len = 0;
+        let mut current = 0;
+
+        loop {
+            let node = &self.nodes[current];
+            // Boxes are tested against the closest hit so far, so far children behind it are skipped.
+            if node.bbox.hit_inv(&orig, &inv_dir, Interval::new_arg(ray_t.min, closest_so_far)) {
+                if node.count > 0 {
+                    let first = node.offset as usize;
+                    for (k, object) in self.primitives[first..first + node.count as usize].iter().enumerate(){
+                        if object.hit(ray, &mut Interval::new_arg(ray_t.min, closest_so_far), rec){
+                            hit_anything = true;
+                            closest_so_far = rec.t;
+                            if let Some(&id) = self.ids.get(first + k) {
+                                rec.object_id = id;
+                            }
+                        }
+                    }
+                } else {
+                    // Descend into the child on the near side of the split first.
+                    let (near, far) = if dir_is_neg[node.axis as usize] {
+                        (node.offset as usize, current + 1)
+                    } else {
+                        (current + 1, node.offset as usize)
+                    };
+                    stack[stack_len] = far;
+                    stack_len += 1;
+                    current = near;
+                    continue;
+                }
+            }
+
+            if stack_len == 0 {
+                break;
+            }
+            stack_len -= 1;
+            current = stack[stack_len];
+        }
+
+        hit_anything
+    }
+
+    fn bounding_box(&self) -> AABB {
+        if self.nodes.is_empty() { AABB::new() } else { self.nodes[0].bbox }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::Lambertian;
+    use crate::ray::Ray;
+    use crate::sphere::Sphere;
+
+    #[test]
+    fn shared_centroids(){
+        // Nested spheres around one point give SAH next to nothing to split on; the tree must still
+        // fit the traversal stack and find the same hits as testing every sphere in turn.
+        let mat = Arc::new(Lambertian::new(Color::new_arg(0.5, 0.5, 0.5)));
+        let mut list = HittableList::new();
+        for i in 0..5000 {
+            list.add(Arc::new(Sphere::new(Point3::new_arg(1.0, 2.0, 3.0), 1.0 + i as f64 * 0.01, mat.clone())));
+        }
+        let flat = HittableList::new_arg(list.objects.clone());
+
+        for method in [SplitMethod::Sah, SplitMethod::Median] {
+            let (bvh, stats) = LinearBvh::new_list_method(HittableList::new_arg(list.objects.clone()), method);
+            assert!(stats.depth <= LinearBvh::STACK_SIZE, "depth {} with {:?}", stats.depth, method);
+
+            for origin in [Point3::new_arg(1.0, 2.0, -100.0), Point3::new_arg(1.0, 2.0, 3.0), Point3::new_arg(90.0, -40.0, 10.0)] {
+                let direction = Point3::new_arg(1.0, 2.0, 3.0) - origin + Rvec3::new_arg(0.1, 0.0, 0.0);
+                let (mut a, mut b) = (HitRecord::new(), HitRecord::new());
+                let hit_bvh = bvh.hit(&mut Ray::new_arg(origin, direction), &mut Interval::new_arg(0.001, INFINITY), &mut a);
+                let hit_flat = flat.hit(&mut Ray::new_arg(origin, direction), &mut Interval::new_arg(0.001, INFINITY), &mut b);
+                assert_eq!(hit_bvh, hit_flat);
+                assert_eq!(a.t, b.t);
+            }
+        }
+    }
+}
@@ -6,24 +6,96 @@ use crate::rvec3::*;
 use crate::hit::*;
 use crate::hitlist::*;
 use crate::ray::*;
+use crate::film::*;
+use crate::material::ScatterRecord;
+use crate::pdf::*;
+use crate::environment::*;
+use crate::light::Light;
+use crate::sampler::*;
+use crate::filter::*;
+use crate::aov::*;
+
+use std::time::{Duration, Instant};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::sync::Arc;
+use std::path::{Path, PathBuf};
+use std::io::IsTerminal;
+
+// Samples an adaptive pixel takes between two checks of its error.
+const ADAPTIVE_BATCH : i32 = 8;
+
+// Samples per pixel in each pass of a render with a time budget but no pass size, so that
+// running out of time never leaves part of the image without samples.
+const TIMED_PASS_SAMPLES : i32 = 4;
+
+// Sampler dimensions used by the camera ray (pixel position, lens, time), and reserved for each
+// bounce after it. A bounce takes at most 7: light choice, point on the light, mixture choice,
+// scattered direction and Russian roulette.
+const CAMERA_DIMENSIONS : u32 = 5;
+const BOUNCE_DIMENSIONS : u32 = 8;
+
+// How the scene is projected onto the image.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Projection{
+    Perspective, // Rays fan out from lookfrom to cover vfov
+    Orthographic, // Parallel rays along the view direction, covering view_width or view_height
+}
 
-use std::time::{Instant};
+impl Projection{
+    pub fn from_name(name : &str) -> Option<Projection>{
+        match name {
+            "perspective" => Some(Projection::Perspective),
+            "orthographic" => Some(Projection::Orthographic),
+            _ => None,
+        }
+    }
+}
+
+// A rectangular block of pixels rendered as one unit of work.
+#[derive(Clone, Copy)]
+pub struct Tile{
+    pub x0 : i32,
+    pub y0 : i32,
+    pub x1 : i32, // exclusive
+    pub y1 : i32, // exclusive
+}
 
 pub struct Camera{
     pub aspect_ratio : f64,  // Ratio of image width over height
     pub image_width : i32,  // Rendered image width in pixel count
-    pub samples_per_pixel : i32, // anti-aliasing
-    pub max_depth : i32, // max depth for recursion
+    pub samples_per_pixel : i32, // anti-aliasing; the most any pixel gets when sampling adaptively
+    pub adaptive_error : f64, // Relative error at which a pixel stops sampling, 0 to always take samples_per_pixel
+    pub min_samples_per_pixel : i32, // Samples every pixel takes before its error is trusted
+    pub max_depth : i32, // Hard limit on the number of bounces of a path
+    pub russian_roulette_depth : i32, // Bounces before dim paths may be ended at random
     
     pub vfov : f64, //vertifcal field of view
     pub lookfrom : Point3, // Point camera is looking from
     pub lookat : Point3, // Point camera is looking at
     pub vup : Rvec3,   // Camera-relative "up" direction
 
+    pub projection : Projection, // Perspective or orthographic
+    pub view_width : Option<f64>, // Orthographic view-plane width in world units
+    pub view_height : Option<f64>, // Orthographic view-plane height in world units, used over view_width
+
     pub defocus_angle : f64, // Variation angle of rays through each pixel
     pub focus_dist : f64, // Distance from camera lookfrom point to plane of perfect focus
 
-    pub background : Color, // background color >_<
+    pub environment : Arc<dyn Environment>, // What rays that leave the scene see
+
+    pub pass_samples : i32, // Samples per pixel in each progressive pass, 0 to render in a single pass
+    pub time_budget : Option<Duration>, // Stop after the pass that runs past this much wall-clock time
+    pub checkpoint : Option<PathBuf>, // Where to save the film between passes, for resuming
+    pub checkpoint_interval : Duration, // Least time between two checkpoints
+
+    pub threads : usize, // Number of worker threads used by render
+    pub tile_size : i32, // Width and height of a render tile in pixels
+    pub seed : u64, // Base seed for the per-pixel, per-sample random streams
+    pub sampler : SamplerType, // How the random numbers of each sample are spread out
+    pub filter : FilterType, // How samples are weighted into the pixels around them
+    pub filter_radius : Option<f64>, // Filter radius in pixels, None for the filter's default
+    pub aovs : Vec<AovType>, // Extra passes to record as layers of the film
 
     image_height : i32,   // Rendered image height
     center : Point3,         // Camera center
@@ -37,6 +109,9 @@ pub struct Camera{
 
     defocus_disk_u : Rvec3, // Defocus disk horizontal radius
     defocus_disk_v : Rvec3, // Defocus disk vertical radius
+
+    pixel_sampler : Arc<dyn Sampler>, // Built from `sampler` for the current render
+    pixel_filter : Arc<dyn Filter>, // Built from `filter` and `filter_radius`
 }
 
 impl Default for Camera{
@@ -51,16 +126,36 @@ impl Camera{
             aspect_ratio : 1.0,
             image_width : 100,
             samples_per_pixel : 10,
+            adaptive_error : 0.0,
+            min_samples_per_pixel : 16,
             max_depth : 10,
+            russian_roulette_depth : 5,
             vfov : 90.0, 
             lookfrom : Point3::new_arg(0.0,0.0,-1.0),
             lookat : Point3::new_arg(0.0,0.0,0.0),
             vup : Rvec3::new_arg(0.0,1.0,0.0),
 
+            projection : Projection::Perspective,
+            view_width : None,
+            view_height : None,
+
             defocus_angle : 0.0,
             focus_dist : 10.0,
 
-            background : Color::new(),
+            environment : Arc::new(ConstantEnvironment::new(Color::new())),
+
+            pass_samples : 0,
+            time_budget : None,
+            checkpoint : None,
+            checkpoint_interval : Duration::from_secs(60),
+
+            threads : thread::available_parallelism().map_or(1, |n| n.get()),
+            tile_size : 32,
+            seed : 0,
+            sampler : SamplerType::Independent,
+            filter : FilterType::Box,
+            filter_radius : None,
+            aovs : Vec::new(),
 
             image_height : 0,
             center : Point3::new(),
@@ -74,40 +169,244 @@ impl Camera{
 
             defocus_disk_u : Rvec3::new(),
             defocus_disk_v : Rvec3::new(),
+
+            pixel_sampler : Arc::new(IndependentSampler),
+            pixel_filter : Arc::new(BoxFilter::new(0.5)),
         }
     }
 
-    pub fn render(&mut self, world : &mut HittableList) {
+    pub fn render(&mut self, world : &HittableList, lights : &HittableList, delta_lights : &[Arc<dyn Light>]) -> Film {
+        // `lights` holds the emitters to sample directly and `delta_lights` the point, spot and
+        // directional lights; either may be empty.
+        let film = Film::new(self.image_width, self.image_height());
+        self.resume(film, world, lights, delta_lights)
+    }
+
+    pub fn resume(&mut self, mut film : Film, world : &HittableList, lights : &HittableList, delta_lights : &[Arc<dyn Light>]) -> Film {
+        // Keeps adding samples to `film`, e.g. one loaded from a checkpoint, in passes over the
+        // whole image until every pixel is done or the time budget runs out.
         self.initialize();
-    
-        // Render                         
-        println!("P3\n{} {}\n255", &self.image_width,&self.image_height);
+        assert!(film.width() == self.image_width && film.height() == self.image_height, "film size does not match the camera");
+        // Checkpoints don't keep the passes, so after resuming they only cover the new samples.
+        for aov in self.aovs.iter() {
+            film.add_layer(*aov);
+        }
     
         //timing
         let start = Instant::now();
-        
-        for j in 0..self.image_height {
-            eprintln!("\rScanlines remaining: {}", self.image_height-j); 
-            for i in 0..self.image_width{
-                let mut pixel_color = Color::new_arg(0.0,0.0,0.0);
-                for _sample in 0..self.samples_per_pixel {
-                    let mut r = self.get_ray(i,j); 
-                    pixel_color += self.ray_color(&mut r, self.max_depth, world);
+
+        let tiles = self.tiles();
+        let deadline = self.time_budget.map(|budget| start + budget);
+
+        // An environment that can be importance sampled is one more light.
+        let mut lights = HittableList::new_arg(lights.objects.clone());
+        if self.environment.is_sampled() {
+            lights.add(Arc::new(EnvironmentLight::new(self.environment.clone())));
+        }
+
+        let mut last_checkpoint = Instant::now();
+        let mut pass = 0;
+        loop {
+            if self.finished(&film) {
+                break;
+            }
+            let before = film.total_samples();
+            let margin = self.filter_margin();
+            // Filter margins make neighbouring tiles overlap, so they are added in a fixed order to
+            // keep the sums independent of which worker finished first.
+            let mut done = self.render_pass(&film, &tiles, world, &lights, delta_lights);
+            done.sort_unstable_by_key(|(idx, _)| *idx);
+            for (idx, tile_film) in done {
+                film.add_tile(tiles[idx].x0 - margin, tiles[idx].y0 - margin, &tile_film);
+            }
+            if film.total_samples() == before {
+                break;
+            }
+
+            pass += 1;
+            let out_of_time = deadline.is_some_and(|d| Instant::now() >= d);
+            if self.pass_size() < self.samples_per_pixel {
+                eprintln!("\rPass {} done, {:.1} samples per pixel", pass, film.total_samples() as f64 / (self.image_width * self.image_height) as f64);
+            }
+
+            if let Some(path) = &self.checkpoint {
+                if out_of_time || last_checkpoint.elapsed() >= self.checkpoint_interval {
+                    self.save_checkpoint(&film, path);
+                    last_checkpoint = Instant::now();
                 }
-                
-                write_color(&mut pixel_color, self.samples_per_pixel);
             }
+            if out_of_time {
+                eprintln!("\rTime budget used up");
+                break;
+            }
+        }
+
+        // Leave a checkpoint of the finished image too, so it can be refined with more samples later.
+        if let Some(path) = &self.checkpoint {
+            self.save_checkpoint(&film, path);
         }
+        number_materials(&mut film);
         
         eprintln!("\rDone");
+        if self.adaptive_error > 0.0 || self.time_budget.is_some() {
+            eprintln!("Average samples per pixel: {:.1}", film.total_samples() as f64 / (self.image_width * self.image_height) as f64);
+        }
         let duration = start.elapsed();
         eprintln!("Time elapsed in expensive_function() is: {:?}", duration);
+
+        film
     }
 
-    fn get_ray(&mut self, i : i32, j : i32) -> Ray{
-        // Get a randomly sampled camera ray for the pixel at location i,j.
+    fn render_pass(&self, film : &Film, tiles : &[Tile], world : &HittableList, lights : &HittableList, delta_lights : &[Arc<dyn Light>]) -> Vec<(usize, Film)>{
+        let next_tile = AtomicUsize::new(0);
+        let finished_tiles = AtomicUsize::new(0);
+        let threads = self.threads.max(1);
+        // Progress is redrawn in place, so it's only shown on a terminal and only when the
+        // percentage changes.
+        let show_progress = std::io::stderr().is_terminal();
+
+        // Workers pull tiles off a shared counter until none are left, so faster threads pick up more work.
+        thread::scope(|s| {
+            let workers : Vec<_> = (0..threads).map(|_| s.spawn(|| {
+                let mut done = Vec::new();
+                loop {
+                    let idx = next_tile.fetch_add(1, Ordering::Relaxed);
+                    if idx >= tiles.len() {
+                        break;
+                    }
+                    done.push((idx, self.render_tile(film, &tiles[idx], world, lights, delta_lights)));
+                    let finished = finished_tiles.fetch_add(1, Ordering::Relaxed) + 1;
+                    let percent = finished * 100 / tiles.len();
+                    if show_progress && percent != (finished - 1) * 100 / tiles.len() {
+                        eprint!("\rRendering: {:3}%", percent);
+                    }
+                }
+                done
+            })).collect();
+
+            let done = workers.into_iter().flat_map(|w| w.join().unwrap()).collect();
+            if show_progress {
+                eprint!("\r{:16}\r", "");
+            }
+            done
+        })
+    }
+
+    fn finished(&self, film : &Film) -> bool{
+        // Whether no pixel would take another sample, so another pass would add nothing.
+        (0..self.image_height).all(|j| (0..self.image_width).all(|i| {
+            film.sample_count(i, j) as i32 >= self.samples_per_pixel || self.converged(film, i, j)
+        }))
+    }
+
+    fn pass_size(&self) -> i32{
+        // The time budget is only checked between passes, so every pass covers the whole image.
+        if self.pass_samples > 0 {
+            self.pass_samples
+        } else if self.time_budget.is_some() {
+            TIMED_PASS_SAMPLES
+        } else {
+            self.samples_per_pixel
+        }
+    }
+
+    fn save_checkpoint(&self, film : &Film, path : &Path){
+        // A failed checkpoint is reported but does not stop the render.
+        match film.save(path) {
+            Ok(()) => eprintln!("\rCheckpoint written to {}", path.display()),
+            Err(err) => eprintln!("\rFailed to write checkpoint {}: {}", path.display(), err),
+        }
+    }
+
+    fn tiles(&self) -> Vec<Tile>{
+        // Split the image into tile_size x tile_size blocks, clipped at the right and bottom edges.
+        let size = self.tile_size.max(1);
+        let mut tiles = Vec::new();
+        for y0 in (0..self.image_height).step_by(size as usize) {
+            for x0 in (0..self.image_width).step_by(size as usize) {
+                tiles.push(Tile{
+                    x0,
+                    y0,
+                    x1 : (x0 + size).min(self.image_width),
+                    y1 : (y0 + size).min(self.image_height),
+                });
+            }
+        }
+        tiles
+    }
+
+    fn render_tile(&self, film : &Film, tile : &Tile, world : &HittableList, lights : &HittableList, delta_lights : &[Arc<dyn Light>]) -> Film{
+        // Adds one pass worth of samples to every unfinished pixel of the tile. Sample numbers
+        // carry on from the samples the film already has, so passes never repeat a random stream.
+        // Returns just the new samples, splatted into the tile and a margin around it.
+        let margin = self.filter_margin();
+        let (width, height) = (tile.x1 - tile.x0, tile.y1 - tile.y0);
+        let mut progress = film.crop(tile.x0, tile.y0, width, height); // Samples so far, for adaptive sampling
+        let mut splats = Film::new(width + 2 * margin, height + 2 * margin);
+        for aov in self.aovs.iter() {
+            splats.add_layer(*aov);
+        }
+
+        for j in tile.y0..tile.y1 {
+            for i in tile.x0..tile.x1 {
+                let (ti, tj) = (i - tile.x0, j - tile.y0);
+                let first = progress.sample_count(ti, tj) as i32;
+                if self.converged(&progress, ti, tj) {
+                    continue;
+                }
+                for sample in first..(first + self.pass_size()).min(self.samples_per_pixel) {
+                    let pixel = (j * self.image_width + i) as u64;
+                    seed_stream(self.seed, pixel, sample as u64);
+                    start_sample(&self.pixel_sampler, splitmix64(self.seed ^ splitmix64(pixel)), sample as u64);
+
+                    let (px, py) = self.pixel_offset();
+                    let r = self.get_ray(i, j, px, py); 
+                    let mut aov = AovSample::new();
+                    let color = self.ray_color(r, world, lights, delta_lights, &mut aov);
+
+                    progress.record_sample(ti, tj, color);
+                    splats.record_sample(ti + margin, tj + margin, color);
+                    record_aovs(&mut splats, ti + margin, tj + margin, &aov, color);
+                    for dy in -margin..=margin {
+                        for dx in -margin..=margin {
+                            let weight = self.pixel_filter.evaluate(px - dx as f64, py - dy as f64);
+                            if weight != 0.0 {
+                                splats.splat(ti + margin + dx, tj + margin + dy, color, weight);
+                            }
+                        }
+                    }
+
+                    if self.converged(&progress, ti, tj) {
+                        break;
+                    }
+                }
+            }
+        }
+        splats
+    }
+
+    fn filter_margin(&self) -> i32{
+        // How many neighbouring pixels a sample anywhere in its pixel can reach.
+        (self.pixel_filter.radius() - 0.5).ceil().max(0.0) as i32
+    }
+
+    fn converged(&self, film : &Film, i : i32, j : i32) -> bool{
+        // Adaptive sampling checks a pixel once it has its minimum samples, then after every batch.
+        if self.adaptive_error <= 0.0 {
+            return false;
+        }
+        let min = self.min_samples_per_pixel.max(2);
+        let n = film.sample_count(i, j) as i32;
+        if n < min || (n - min) % ADAPTIVE_BATCH != 0 {
+            return false;
+        }
+        film.relative_error(i, j) <= self.adaptive_error
+    }
+
+    fn get_ray(&self, i : i32, j : i32, px : f64, py : f64) -> Ray{
+        // Get a randomly sampled camera ray through offset px,py from the center of pixel i,j.
         let pixel_center = self.pixel00_loc + ( (i as f64)* self.pixel_delta_u) + ((j as f64) * self.pixel_delta_v);
-        let pixel_sample = pixel_center + self.pixel_sample_square();
+        let pixel_sample = pixel_center + (px * self.pixel_delta_u) + (py * self.pixel_delta_v);
 
         let mut ray_origin = self.center;
 
@@ -115,24 +414,34 @@ impl Camera{
         if self.defocus_angle > 0.0{
             ray_origin = self.defocus_disk_sample();
         }
-        
+
+        // Orthographic rays leave from the plane of lookfrom, straight behind their pixel sample.
+        if self.projection == Projection::Orthographic {
+            ray_origin += pixel_sample + self.focus_dist * self.w - self.center;
+        }
         
         let ray_direction = Rvec3::unit_vector(&mut (pixel_sample - ray_origin));
-        let ray_time = random_double();
+        let ray_time = sample_1d();
 
         Ray::new_time(ray_origin,ray_direction,ray_time)
     }
 
-    fn pixel_sample_square(&mut self) -> Rvec3{
-        let px = -0.5 + random_double();
-        let py = -0.5 + random_double();
-        (px * self.pixel_delta_u) + (py * self.pixel_delta_v)
+    fn pixel_offset(&self) -> (f64, f64){
+        // Random offset within the pixel square, in pixels from its center.
+        let (r1, r2) = sample_2d();
+        (-0.5 + r1, -0.5 + r2)
     }
 
-    fn initialize(&mut self){
+    pub fn image_height(&self) -> i32{
         // Calculate the image height, and ensure that it's at least 1.
-        self.image_height =  ((self.image_width as f64)/self.aspect_ratio) as i32;
-        if self.image_height < 1 {self.image_height = 1;}
+        let height = ((self.image_width as f64)/self.aspect_ratio).round() as i32;
+        height.max(1)
+    }
+
+    fn initialize(&mut self){
+        self.image_height = self.image_height();
+        self.pixel_sampler = self.sampler.build(self.samples_per_pixel);
+        self.pixel_filter = self.filter.build(self.filter_radius);
         
         self.center = self.lookfrom;
 
@@ -140,8 +449,15 @@ impl Camera{
         //let focal_length = (self.lookfrom - self.lookat).length();
         let theta = self.vfov.to_radians();
         let h = (theta/2.0).tan();
-        let viewport_height = 2.0 * h  * self.focus_dist;
-        let viewport_width = viewport_height * ((self.image_width as f64)/(self.image_height as f64));
+        let aspect = (self.image_width as f64)/(self.image_height as f64);
+        let viewport_height = match self.projection {
+            Projection::Perspective => 2.0 * h  * self.focus_dist,
+            // Without a size, the orthographic view covers what vfov would around lookat.
+            Projection::Orthographic => self.view_height
+                .or(self.view_width.map(|width| width / aspect))
+                .unwrap_or(2.0 * h * (self.lookfrom - self.lookat).length()),
+        };
+        let viewport_width = viewport_height * aspect;
         
 
         // Calculate the u,v,w unit basis vectors for the camera coordinate frame.
@@ -169,29 +485,148 @@ impl Camera{
         self.defocus_disk_v = self.v * defocus_radius;
     }
 
-    fn ray_color(&self, r : &mut Ray, depth : i32, world : &mut HittableList) -> Color {
-        let mut rec : HitRecord = HitRecord::new(); 
+    fn ray_color(&self, mut ray : Ray, world : &HittableList, lights : &HittableList, delta_lights : &[Arc<dyn Light>], aov : &mut AovSample) -> Color {
+        // Follows one path bounce by bounce. `throughput` is what the path has kept of the light so
+        // far and `emission_weight` the MIS weight of the strategy that produced the current ray
+        // for any light it hits. What the first hit sees goes into `aov`.
+        let mut color = Color::new();
+        let mut direct = None; // `color` once everything up to one bounce is in
+        let mut throughput = Color::new_arg(1.0, 1.0, 1.0);
+        let mut emission_weight = 1.0;
+
+        for depth in 0..self.max_depth {
+            let base = CAMERA_DIMENSIONS + depth as u32 * BOUNCE_DIMENSIONS;
+            debug_assert!(dimension() <= base, "previous bounce used dimensions up to {}, past {}", dimension(), base);
+            set_dimension(base);
+            let mut rec : HitRecord = HitRecord::new();
+
+            // If the ray hits nothing, add what the environment shows in its direction.
+            if !world.hit(&mut ray, &mut Interval::new_arg(0.001, INFINITY), &mut rec){
+                let background = self.environment.value(&ray.direction());
+                if depth == 0 {
+                    aov.record_light(background);
+                }
+                color += throughput * emission_weight * background;
+                break;
+            }
 
-        if depth <= 0 {
-            return Color::new();
-        }
+            let mut srec = ScatterRecord::new();
+            let emitted = rec.mat.emitted(&rec, rec.u, rec.v, &rec.p);
+            color += throughput * emission_weight * emitted;
+            if depth == 0 && rec.medium {
+                if !self.aovs.is_empty() {
+                    self.record_surface_aovs(&mut ray, world, aov);
+                }
+            } else if depth == 0 {
+                aov.record_hit(&rec, Rvec3::dot(&(rec.p - self.center), &-self.w));
+                aov.record_light(emitted);
+            } else if depth == 1 {
+                direct = Some(color);
+            }
 
-         // If the ray hits nothing, return the background color.
-        if !world.hit(r, &mut Interval::new_arg(0.001, INFINITY), &mut rec){
-            return self.background;
-        }
+            if !rec.mat.scatter(&mut ray, &rec, &mut srec){
+                break;
+            }
+            if depth == 0 && !rec.medium {
+                aov.albedo = srec.attenuation;
+            }
+
+            if srec.skip_pdf {
+                // Specular materials pick their own direction and skip light sampling.
+                throughput *= srec.attenuation;
+                emission_weight = 1.0;
+                ray = srec.skip_pdf_ray;
+            } else {
+                let surface_pdf = srec.pdf.expect("material without skip_pdf must set a pdf");
+
+                // Light sampling: one shadow ray towards a light, counting whatever emitter it reaches.
+                if !lights.objects.is_empty() {
+                    let light_pdf = HittablePdf::new(lights, rec.p);
+                    let mut to_light = Ray::new_time(rec.p, light_pdf.generate(), ray.time());
+                    let light_value = light_pdf.value(&to_light.direction());
+                    let scattering_pdf = rec.mat.scattering_pdf(&mut ray, &rec, &mut to_light);
+
+                    if light_value > 0.0 && scattering_pdf > 0.0 {
+                        let mut light_rec = HitRecord::new();
+                        let emitted = if world.hit(&mut to_light, &mut Interval::new_arg(0.001, INFINITY), &mut light_rec) {
+                            light_rec.mat.emitted(&light_rec, light_rec.u, light_rec.v, &light_rec.p)
+                        } else {
+                            self.environment.value(&to_light.direction())
+                        };
+                        let weight = power_heuristic(light_value, surface_pdf.value(&to_light.direction()));
+                        color += throughput * (weight * (srec.attenuation * scattering_pdf * emitted) / light_value);
+                    }
+                }
+
+                // Delta lights can only be reached by a shadow ray, so each one is added in full.
+                for light in delta_lights {
+                    let Some(sample) = light.sample(&rec.p) else { continue };
+                    let mut to_light = Ray::new_time(rec.p, sample.direction, ray.time());
+                    let scattering_pdf = rec.mat.scattering_pdf(&mut ray, &rec, &mut to_light);
+                    if scattering_pdf <= 0.0 {
+                        continue;
+                    }
+                    let mut shadow_rec = HitRecord::new();
+                    if !world.hit(&mut to_light, &mut Interval::new_arg(0.001, sample.distance - 0.001), &mut shadow_rec) {
+                        color += throughput * srec.attenuation * scattering_pdf * sample.irradiance;
+                    }
+                }
+
+                // Material sampling: continue the path, weighting any light it hits against light sampling.
+                let mut scattered = Ray::new_time(rec.p, surface_pdf.generate(), ray.time());
+                let pdf_value = surface_pdf.value(&scattered.direction());
+                if pdf_value <= 0.0 {
+                    break;
+                }
+
+                let scattering_pdf = rec.mat.scattering_pdf(&mut ray, &rec, &mut scattered);
+                if scattering_pdf <= 0.0 {
+                    break;
+                }
 
-        let mut scattered = Ray::new();
-        let mut attenuation = Color::new();
-        let color_from_emission = rec.mat.borrow_mut().emitted(rec.u, rec.v, &rec.p);
+                emission_weight = if lights.objects.is_empty() {
+                    1.0
+                } else {
+                    power_heuristic(pdf_value, lights.pdf_value(&rec.p, &scattered.direction()))
+                };
+                throughput = throughput * srec.attenuation * (scattering_pdf / pdf_value);
+                ray = scattered;
+            }
 
-        if !rec.mat.borrow_mut().scatter(r, &rec, &mut attenuation, &mut scattered){
-            return color_from_emission;
+            // Russian roulette: past the minimum depth, end dim paths at random and boost the
+            // survivors so the estimate stays unbiased.
+            if depth + 1 >= self.russian_roulette_depth {
+                let survive = throughput.e[0].max(throughput.e[1]).max(throughput.e[2]).min(1.0);
+                if sample_1d() >= survive {
+                    break;
+                }
+                throughput /= survive;
+            }
         }
 
-        let color_from_scatter = attenuation * self.ray_color(&mut scattered, depth-1, world);
+        aov.direct = direct.unwrap_or(color);
+        color
+    }
 
-        color_from_emission + color_from_scatter
+    fn record_surface_aovs(&self, ray : &mut Ray, world : &HittableList, aov : &mut AovSample){
+        // The camera ray scattered in a medium, which lands at a random depth; the passes show
+        // the first surface behind it instead, so they stay free of noise for the denoiser.
+        let mut ray = Ray::new_time(ray.origin(), ray.direction(), ray.time());
+        let mut rec = HitRecord::new();
+        if !hit_surface(world, &mut ray, &mut Interval::new_arg(0.001, INFINITY), &mut rec) {
+            aov.record_light(self.environment.value(&ray.direction()));
+            return;
+        }
+        aov.record_hit(&rec, Rvec3::dot(&(rec.p - self.center), &-self.w));
+        aov.record_light(rec.mat.emitted(&rec, rec.u, rec.v, &rec.p));
+
+        // Scattering may draw sampler dimensions, which the path itself still needs.
+        let dim = dimension();
+        let mut srec = ScatterRecord::new();
+        if rec.mat.scatter(&mut ray, &rec, &mut srec) {
+            aov.albedo = srec.attenuation;
+        }
+        set_dimension(dim);
     }
 
     pub fn defocus_disk_sample(&self)  -> Point3{
@@ -200,4 +635,41 @@ impl Camera{
         self.center + (p.e[0] * self.defocus_disk_u) + (p.e[1] * self.defocus_disk_v)
     }
 
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hit::ConstantMedium;
+    use crate::material::Lambertian;
+    use crate::quad::Quad;
+
+    #[test]
+    fn guides_see_through_media(){
+        // A grey wall seen through dense white fog: nearly every camera ray scatters in the fog
+        // at a random depth, but the albedo and depth passes should show the wall alone.
+        let mut world = HittableList::new();
+        let grey = Arc::new(Lambertian::new(Color::new_arg(0.5, 0.5, 0.5)));
+        world.add(Arc::new(Quad::new(Point3::new_arg(-100.0, -100.0, -10.0), Rvec3::new_arg(200.0, 0.0, 0.0), Rvec3::new_arg(0.0, 200.0, 0.0), grey.clone())));
+        let fog = HittableList::box_new(&mut Point3::new_arg(-100.0, -100.0, -8.0), &mut Point3::new_arg(100.0, 100.0, -2.0), grey);
+        world.add(Arc::new(ConstantMedium::new_col(fog, 1.0, Color::new_arg(1.0, 1.0, 1.0))));
+
+        let mut cam = Camera::new();
+        cam.image_width = 8;
+        cam.samples_per_pixel = 8;
+        cam.lookfrom = Point3::new_arg(0.0, 0.0, 0.0);
+        cam.lookat = Point3::new_arg(0.0, 0.0, -1.0);
+        cam.vfov = 40.0;
+        cam.threads = 1;
+        cam.aovs = vec![AovType::Albedo, AovType::Depth];
+        let film = cam.render(&world, &HittableList::new(), &[]);
+
+        let albedo = film.layer(AovType::Albedo).unwrap();
+        let depth = film.layer(AovType::Depth).unwrap();
+        for j in 0..film.height() {
+            for i in 0..film.width() {
+                assert!((albedo.pixel(i, j) - Color::new_arg(0.5, 0.5, 0.5)).length() < 1e-9, "albedo at {}, {}", i, j);
+                assert!((depth.pixel(i, j)[0] - 10.0).abs() < 1e-9, "depth at {}, {}", i, j);
+            }
+        }
+    }
+}
@@ -0,0 +1,195 @@
+use std::path::PathBuf;
+
+use rtracer::sampler::SamplerType;
+use rtracer::filter::FilterType;
+use rtracer::tonemap::ToneMapType;
+use rtracer::aov::AovType;
+use rtracer::camera::Projection;
+
+pub const USAGE : &str = "\
+Usage: rtracer [OPTIONS] [SCENE]
+
+Renders SCENE, which is either the name of a built-in scene or the path of a
+.toml scene file (cornell_box by default).
+
+Options:
+  -o, --output <PATH>   Write the image to PATH; the extension picks the format
+                        (.ppm, .png, .jpg, .hdr, .pfm, .exr). Without it a PPM
+                        image is printed to stdout
+  -w, --width <PX>      Image width in pixels
+      --height <PX>     Image height in pixels (overrides the scene's aspect ratio)
+  -s, --spp <N>         Samples per pixel (the most per pixel with --adaptive)
+      --adaptive <ERR>  Keep sampling each pixel until the relative error of its
+                        mean drops to ERR (e.g. 0.02)
+      --min-spp <N>     Samples per pixel before --adaptive may stop (16)
+      --sample-map <PATH>
+                        Also write the number of samples of each pixel to PATH
+  -d, --depth <N>       Maximum ray bounce depth
+      --rr-depth <N>    Bounces before Russian roulette may end a path
+      --pass-spp <N>    Render in progressive passes of N samples per pixel
+      --time <SECONDS>  Stop after the first pass that ends past this much time,
+                        keeping the samples taken so far. Without --pass-spp the
+                        passes are 4 samples per pixel
+      --checkpoint <PATH>
+                        Save the accumulated samples to PATH after passes, for --resume
+      --checkpoint-every <SECONDS>
+                        Least time between two checkpoints (60)
+      --resume <PATH>   Continue from a checkpoint of the same scene and size; keeps
+                        checkpointing to PATH unless --checkpoint is given
+      --projection <NAME>
+                        Camera projection: perspective or orthographic
+      --view-width <W>  Width of the orthographic view in world units
+      --view-height <H> Height of the orthographic view in world units (by default
+                        what the field of view covers at the point looked at)
+      --sampler <NAME>  Sample pattern: independent, stratified, halton or sobol
+      --filter <NAME>   Pixel filter: box, tent, gaussian, mitchell or lanczos
+      --filter-radius <PX>
+                        Filter radius in pixels (0.5, 1, 1.5, 2 and 2 by default)
+      --tonemap <NAME>  Tone mapping for 8-bit output: clamp (the default), reinhard,
+                        reinhard-extended, aces, hable or agx
+      --exposure <STOPS>
+                        Scale the radiance by 2^STOPS before tone mapping
+      --white <L>       Radiance that maps to white with reinhard-extended (the
+                        brightest pixel by default) and hable (11.2)
+      --aov <NAMES>     Also record these comma-separated passes, or all of them:
+                        albedo, normal, depth, position, uv, object, material,
+                        direct, indirect. They become layers of an .exr output,
+                        otherwise files next to it (img.albedo.png)
+      --aov-files       Write the passes as separate files even with .exr output
+      --denoise         Denoise the image after rendering, guided by its albedo,
+                        normal and depth passes
+      --seed <N>        Seed for the scene layout and the sample streams
+  -t, --threads <N>     Number of render threads
+  -l, --list            List the built-in scenes and exit
+  -h, --help            Print this help and exit";
+
+// Everything the user asked for on the command line. Unset options keep the scene's own values.
+#[derive(Default)]
+pub struct Options{
+    pub scene : Option<String>,
+    pub output : Option<PathBuf>,
+    pub width : Option<i32>,
+    pub height : Option<i32>,
+    pub samples_per_pixel : Option<i32>,
+    pub adaptive_error : Option<f64>,
+    pub min_samples_per_pixel : Option<i32>,
+    pub sample_map : Option<PathBuf>,
+    pub max_depth : Option<i32>,
+    pub russian_roulette_depth : Option<i32>,
+    pub pass_samples : Option<i32>,
+    pub time_budget : Option<f64>,
+    pub checkpoint : Option<PathBuf>,
+    pub checkpoint_interval : Option<f64>,
+    pub resume : Option<PathBuf>,
+    pub projection : Option<Projection>,
+    pub view_width : Option<f64>,
+    pub view_height : Option<f64>,
+    pub sampler : Option<SamplerType>,
+    pub filter : Option<FilterType>,
+    pub filter_radius : Option<f64>,
+    pub tone_map : Option<ToneMapType>,
+    pub exposure : Option<f64>,
+    pub white : Option<f64>,
+    pub aovs : Vec<AovType>,
+    pub aov_files : bool,
+    pub denoise : bool,
+    pub seed : Option<u64>,
+    pub threads : Option<usize>,
+    pub list : bool,
+    pub help : bool,
+}
+
+impl Options{
+    pub fn parse(args : impl IntoIterator<Item = String>) -> Result<Options, String>{
+        let mut opts = Options::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-h" | "--help" => opts.help = true,
+                "-l" | "--list" => opts.list = true,
+                "-o" | "--output" => opts.output = Some(PathBuf::from(value(&arg, args.next())?)),
+                "-w" | "--width" => opts.width = Some(positive(&arg, args.next())?),
+                "--height" => opts.height = Some(positive(&arg, args.next())?),
+                "-s" | "--spp" => opts.samples_per_pixel = Some(positive(&arg, args.next())?),
+                "--adaptive" => opts.adaptive_error = Some(positive(&arg, args.next())?),
+                "--min-spp" => opts.min_samples_per_pixel = Some(positive(&arg, args.next())?),
+                "--sample-map" => opts.sample_map = Some(PathBuf::from(value(&arg, args.next())?)),
+                "-d" | "--depth" => opts.max_depth = Some(positive(&arg, args.next())?),
+                "--rr-depth" => opts.russian_roulette_depth = Some(number(&arg, args.next())?),
+                "--pass-spp" => opts.pass_samples = Some(positive(&arg, args.next())?),
+                "--time" => opts.time_budget = Some(positive(&arg, args.next())?),
+                "--checkpoint" => opts.checkpoint = Some(PathBuf::from(value(&arg, args.next())?)),
+                "--checkpoint-every" => opts.checkpoint_interval = Some(positive(&arg, args.next())?),
+                "--resume" => opts.resume = Some(PathBuf::from(value(&arg, args.next())?)),
+                "--projection" => {
+                    let name = value(&arg, args.next())?;
+                    opts.projection = Some(Projection::from_name(&name).ok_or_else(|| format!("unknown projection '{}'", name))?);
+                }
+                "--view-width" => opts.view_width = Some(positive(&arg, args.next())?),
+                "--view-height" => opts.view_height = Some(positive(&arg, args.next())?),
+                "--sampler" => {
+                    let name = value(&arg, args.next())?;
+                    opts.sampler = Some(SamplerType::from_name(&name).ok_or_else(|| format!("unknown sampler '{}'", name))?);
+                }
+                "--filter" => {
+                    let name = value(&arg, args.next())?;
+                    opts.filter = Some(FilterType::from_name(&name).ok_or_else(|| format!("unknown filter '{}'", name))?);
+                }
+                "--filter-radius" => opts.filter_radius = Some(positive(&arg, args.next())?),
+                "--tonemap" => {
+                    let name = value(&arg, args.next())?;
+                    opts.tone_map = Some(ToneMapType::from_name(&name).ok_or_else(|| format!("unknown tone mapping '{}'", name))?);
+                }
+                "--exposure" => opts.exposure = Some(number(&arg, args.next())?),
+                "--white" => opts.white = Some(positive(&arg, args.next())?),
+                "--aov" => {
+                    for name in value(&arg, args.next())?.split(',') {
+                        let aovs = match name {
+                            "all" => AovType::ALL.to_vec(),
+                            _ => vec![AovType::from_name(name).ok_or_else(|| format!("unknown pass '{}'", name))?],
+                        };
+                        for aov in aovs {
+                            if !opts.aovs.contains(&aov) {
+                                opts.aovs.push(aov);
+                            }
+                        }
+                    }
+                }
+                "--aov-files" => opts.aov_files = true,
+                "--denoise" => opts.denoise = true,
+                "--seed" => opts.seed = Some(number(&arg, args.next())?),
+                "-t" | "--threads" => opts.threads = Some(positive(&arg, args.next())?),
+                _ if arg.starts_with('-') => return Err(format!("unknown option '{}'", arg)),
+                _ => {
+                    if opts.scene.is_some() {
+                        return Err(format!("unexpected argument '{}'", arg));
+                    }
+                    opts.scene = Some(arg);
+                }
+            }
+        }
+
+        if opts.view_width.is_some() && opts.view_height.is_some() {
+            return Err("give either '--view-width' or '--view-height', not both".to_string());
+        }
+        Ok(opts)
+    }
+}
+
+fn value(flag : &str, next : Option<String>) -> Result<String, String>{
+    next.ok_or_else(|| format!("'{}' needs a value", flag))
+}
+
+fn number<T : std::str::FromStr>(flag : &str, next : Option<String>) -> Result<T, String>{
+    let text = value(flag, next)?;
+    text.parse().map_err(|_| format!("'{}' expects a number, got '{}'", flag, text))
+}
+
+fn positive<T : std::str::FromStr + PartialOrd + Default>(flag : &str, next : Option<String>) -> Result<T, String>{
+    let n : T = number(flag, next)?;
+    if n <= T::default() {
+        return Err(format!("'{}' must be greater than zero", flag));
+    }
+    Ok(n)
+}
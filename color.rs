@@ -1,30 +1,35 @@
 use super::rvec3::*;
 use crate::interval::*;
+use std::io::{self, Write};
 pub type Color = Rvec3;
 
-pub fn linear_to_gamma(linear_component : f64) -> f64 {
-    linear_component.sqrt()
+pub fn linear_to_srgb(linear_component : f64) -> f64 {
+    // The sRGB transfer function: a short linear segment near black, then a 2.4 power curve.
+    if linear_component <= 0.0031308 {
+        12.92 * linear_component
+    } else {
+        1.055 * linear_component.powf(1.0 / 2.4) - 0.055
+    }
 }
 
-
-pub fn write_color(pixel_color : &mut Color, samples_per_pixel : i32){
-    let mut r = pixel_color.x();
-    let mut g = pixel_color.y();
-    let mut b = pixel_color.z();
-
-    // Divide the color by the number of samples.
-    let scale = 1.0 / (samples_per_pixel as f64);
-    r *= scale;
-    g *= scale;
-    b *= scale;
-
-
-    // Apply the linear to gamma transform.
-    r = linear_to_gamma(r);
-    g = linear_to_gamma(g);
-    b = linear_to_gamma(b);
+pub fn luminance(c : Color) -> f64{
+    // Relative luminance of linear sRGB, ignoring negative values.
+    (0.2126 * c.e[0] + 0.7152 * c.e[1] + 0.0722 * c.e[2]).max(0.0)
+}
 
 
-    let mut intensity = Interval::new_arg(0.0,0.999);
-    println!("{} {} {}", (256.0 * intensity.clamp(r)) as i32, (256.0 * intensity.clamp(g)) as i32, (256.0 * intensity.clamp(b)) as i32);
+pub fn to_rgb8(pixel_color : Color) -> [u8; 3]{
+    // Clamp a display-referred color to [0, 1], encode it as sRGB and round to 8 bits.
+    let mut intensity = Interval::new_arg(0.0,1.0);
+    let mut rgb = [0u8; 3];
+    for c in 0..3 {
+        rgb[c] = (255.0 * linear_to_srgb(intensity.clamp(pixel_color[c])) + 0.5) as u8;
+    }
+    rgb
 }
+
+pub fn write_color(out : &mut impl Write, pixel_color : Color) -> io::Result<()>{
+    // Write one averaged pixel as a line of a P3 image.
+    let [r, g, b] = to_rgb8(pixel_color);
+    writeln!(out, "{} {} {}", r, g, b)
+}
\ No newline at end of file
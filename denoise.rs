@@ -0,0 +1,161 @@
+// Denoiser run on a finished film, guided by its albedo, normal and depth passes.
+//
+// An edge-avoiding À-trous wavelet filter (Dammertz et al., "Edge-Avoiding À-Trous Wavelet
+// Transform for fast Global Illumination Filtering", 2010): a 5x5 blur whose taps spread twice
+// as far apart every iteration, with each tap weighted down when its albedo, normal, depth or
+// brightness differs from the center's. The brightness test is scaled by each pixel's own noise,
+// estimated from its samples and filtered along with the image, as in SVGF (Schied et al., 2017).
+
+use crate::aov::AovType;
+use crate::color::*;
+use crate::film::*;
+use crate::rvec3::*;
+
+use std::thread;
+
+const KERNEL : [f64; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+const ITERATIONS : u32 = 5; // The last one reaches 2 * 16 pixels out
+const SIGMA_LUMINANCE : f64 = 4.0; // Brightness differences tolerated, in standard deviations
+const SIGMA_ALBEDO : f64 = 0.1; // Albedo differences tolerated
+const SIGMA_NORMAL : f64 = 128.0; // Exponent of the normals' cosine
+const SIGMA_DEPTH : f64 = 2.0; // Depth differences tolerated, relative to the local slope
+
+// The passes that tell the filter where the edges are.
+struct Guides{
+    width : i32,
+    height : i32,
+    albedo : Vec<Color>,
+    normal : Vec<Rvec3>,
+    depth : Vec<f64>,
+    slope : Vec<f64>, // How fast depth changes around each pixel
+}
+
+impl Guides{
+    fn new(film : &Film) -> Self{
+        let (width, height) = (film.width(), film.height());
+        let pass = |aov : AovType| -> Vec<Color> {
+            match film.layer(aov) {
+                Some(layer) => (0..width * height).map(|k| layer.pixel(k % width, k / width)).collect(),
+                None => vec![Color::new(); (width * height) as usize],
+            }
+        };
+
+        let albedo = pass(AovType::Albedo);
+        let normal = pass(AovType::Normal);
+        let depth : Vec<f64> = pass(AovType::Depth).iter().map(|d| d[0]).collect();
+
+        // Slanted surfaces change depth quickly without being edges.
+        let mut slope = vec![0.0; depth.len()];
+        let at = |i : i32, j : i32| (j * width + i) as usize;
+        for j in 0..height {
+            for i in 0..width {
+                let dx = (depth[at((i + 1).min(width - 1), j)] - depth[at((i - 1).max(0), j)]).abs();
+                let dy = (depth[at(i, (j + 1).min(height - 1))] - depth[at(i, (j - 1).max(0))]).abs();
+                slope[at(i, j)] = 0.5 * dx.max(dy);
+            }
+        }
+
+        Self { width, height, albedo, normal, depth, slope }
+    }
+
+    fn filter_pixel(&self, color : &[Color], variance : &[f64], step : i32, i : i32, j : i32) -> (Color, f64){
+        // One À-trous tap pattern around pixel i,j: its new color and the variance of that.
+        let p = (j * self.width + i) as usize;
+        let l_p = luminance(color[p]);
+        // One pixel's variance is a poor guide, so look at the 3x3 average.
+        let spread = SIGMA_LUMINANCE * self.blurred_variance(variance, i, j).sqrt() + 1e-6;
+
+        let mut sum = Color::new();
+        let mut sum_weight = 0.0;
+        let mut sum_variance = 0.0;
+        for (ky, hy) in KERNEL.iter().enumerate() {
+            for (kx, hx) in KERNEL.iter().enumerate() {
+                let (dx, dy) = ((kx as i32 - 2) * step, (ky as i32 - 2) * step);
+                let (x, y) = (i + dx, j + dy);
+                if x < 0 || y < 0 || x >= self.width || y >= self.height {
+                    continue;
+                }
+                let q = (y * self.width + x) as usize;
+                let mut weight = hx * hy;
+                if q != p {
+                    let distance = ((dx * dx + dy * dy) as f64).sqrt();
+                    let albedo_change = self.albedo[p] - self.albedo[q];
+                    weight *= (-Rvec3::dot(&albedo_change, &albedo_change) / (SIGMA_ALBEDO * SIGMA_ALBEDO)).exp()
+                        * normal_weight(self.normal[p], self.normal[q])
+                        * (-(self.depth[p] - self.depth[q]).abs() / (SIGMA_DEPTH * self.slope[p] * distance + 1e-6)).exp()
+                        * (-(l_p - luminance(color[q])).abs() / spread).exp();
+                }
+                sum += weight * color[q];
+                sum_weight += weight;
+                sum_variance += weight * weight * variance[q];
+            }
+        }
+        (sum / sum_weight, sum_variance / (sum_weight * sum_weight))
+    }
+
+    fn blurred_variance(&self, variance : &[f64], i : i32, j : i32) -> f64{
+        let mut sum = 0.0;
+        let mut count = 0.0;
+        for y in (j - 1).max(0)..=(j + 1).min(self.height - 1) {
+            for x in (i - 1).max(0)..=(i + 1).min(self.width - 1) {
+                sum += variance[(y * self.width + x) as usize];
+                count += 1.0;
+            }
+        }
+        sum / count
+    }
+}
+
+pub fn denoise(film : &mut Film, threads : usize){
+    // Replaces the film's pixels by denoised ones, filtering bands of rows on `threads` threads.
+    // Missing passes just aren't used, but without them edges get blurred.
+    let guides = Guides::new(film);
+    let (width, height) = (film.width(), film.height());
+
+    let mut color = Vec::with_capacity((width * height) as usize);
+    let mut variance = Vec::with_capacity((width * height) as usize);
+    for j in 0..height {
+        for i in 0..width {
+            color.push(film.pixel(i, j));
+            // Pixels with too few samples to tell get a variance that lets everything through.
+            let v = film.variance(i, j);
+            variance.push(if v.is_finite() { v } else { 1e6 });
+        }
+    }
+
+    let band = (width as usize) * (height as usize).div_ceil(threads.max(1)).max(1);
+    for iteration in 0..ITERATIONS {
+        let step = 1 << iteration;
+        let mut next_color = vec![Color::new(); color.len()];
+        let mut next_variance = vec![0.0; variance.len()];
+        thread::scope(|s| {
+            for (b, (colors, variances)) in next_color.chunks_mut(band).zip(next_variance.chunks_mut(band)).enumerate() {
+                let (guides, color, variance) = (&guides, &color, &variance);
+                s.spawn(move || {
+                    for (k, (c, v)) in colors.iter_mut().zip(variances.iter_mut()).enumerate() {
+                        let p = (b * band + k) as i32;
+                        (*c, *v) = guides.filter_pixel(color, variance, step, p % width, p / width);
+                    }
+                });
+            }
+        });
+        color = next_color;
+        variance = next_variance;
+    }
+
+    for j in 0..height {
+        for i in 0..width {
+            film.set_pixel(i, j, color[(j * width + i) as usize]);
+        }
+    }
+}
+
+fn normal_weight(mut a : Rvec3, mut b : Rvec3) -> f64{
+    // Background pixels have no normal; they only blend with each other.
+    let (a_none, b_none) = (a.near_zero(), b.near_zero());
+    if a_none || b_none {
+        return if a_none && b_none { 1.0 } else { 0.0 };
+    }
+    let cos = Rvec3::dot(&a, &b) / (a.length() * b.length());
+    cos.max(0.0).powf(SIGMA_NORMAL)
+}
@@ -0,0 +1,251 @@
+// What rays that leave the scene see: a constant colour or an image-based environment.
+//
+// An environment that can be importance sampled is added to the camera's light list through
+// EnvironmentLight, so light sampling and MIS treat it like any other emitter.
+
+use crate::rvec3::*;
+use crate::color::*;
+use crate::hit::*;
+use crate::ray::Ray;
+use crate::interval::Interval;
+use crate::aabb::AABB;
+use crate::utility::degrees_to_radians;
+use crate::sampler::sample_2d;
+
+use std::f64::consts::PI;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::Arc;
+
+use image::codecs::hdr::HdrDecoder;
+
+pub trait Environment : Send + Sync {
+    // Radiance arriving from `direction` (pointing away from the scene).
+    fn value(&self, direction : &Rvec3) -> Color;
+
+    // Whether pdf_value and random can be used to sample the environment as a light.
+    fn is_sampled(&self) -> bool {
+        false
+    }
+
+    // Solid-angle density of `random` picking `direction`.
+    fn pdf_value(&self, _direction : &Rvec3) -> f64 {
+        0.0
+    }
+
+    fn random(&self) -> Rvec3 {
+        Rvec3::new_arg(0.0, 1.0, 0.0)
+    }
+}
+
+// The same colour in every direction, like the old Camera::background.
+pub struct ConstantEnvironment{
+    color : Color,
+}
+
+impl ConstantEnvironment{
+    pub fn new(c : Color) -> Self{
+        Self { color : c }
+    }
+}
+
+impl Environment for ConstantEnvironment{
+    fn value(&self, _direction : &Rvec3) -> Color {
+        self.color
+    }
+}
+
+// Piecewise-constant 1D distribution over [0, 1), sampled by inverting its CDF.
+struct Distribution1D{
+    func : Vec<f64>,
+    cdf : Vec<f64>, // func.len() + 1 entries, from 0 to 1
+    func_int : f64, // Integral of func over [0, 1)
+}
+
+impl Distribution1D{
+    fn new(func : Vec<f64>) -> Self{
+        let n = func.len();
+        let mut cdf = vec![0.0; n + 1];
+        for i in 0..n {
+            cdf[i + 1] = cdf[i] + func[i] / n as f64;
+        }
+        let func_int = cdf[n];
+        for (i, c) in cdf.iter_mut().enumerate() {
+            // An all-black row is sampled uniformly.
+            *c = if func_int == 0.0 { i as f64 / n as f64 } else { *c / func_int };
+        }
+        Self { func, cdf, func_int }
+    }
+
+    fn count(&self) -> usize{
+        self.func.len()
+    }
+
+    fn pdf(&self, i : usize) -> f64{
+        if self.func_int == 0.0 { 1.0 } else { self.func[i] / self.func_int }
+    }
+
+    fn sample(&self, u : f64) -> (f64, usize){
+        // Returns the sampled position in [0, 1) and the index of its bucket.
+        let i = self.cdf.partition_point(|&c| c <= u).clamp(1, self.count()) - 1;
+        let width = self.cdf[i + 1] - self.cdf[i];
+        let du = if width > 0.0 { (u - self.cdf[i]) / width } else { 0.0 };
+        ((i as f64 + du) / self.count() as f64, i)
+    }
+}
+
+// Equirectangular (latitude-longitude) HDR image around the scene, laid out like the sphere
+// UVs: the top row looks along +y, the left edge along -x and the middle column along +x.
+// `rotation` turns the map about +y, in degrees.
+pub struct EnvironmentMap{
+    width : usize,
+    height : usize,
+    pixels : Vec<Color>,
+    intensity : f64,
+    sin_rotation : f64,
+    cos_rotation : f64,
+    rows : Distribution1D,      // Marginal density of picking a row
+    columns : Vec<Distribution1D>, // Density of each column within a row
+}
+
+impl EnvironmentMap{
+    pub fn load(filename : &str, rotation : f64, intensity : f64) -> image::ImageResult<Self>{
+        // The generic decoder turns Radiance files into 8-bit images, so read those directly.
+        let is_hdr = Path::new(filename).extension().is_some_and(|ext| ext.eq_ignore_ascii_case("hdr"));
+        let (width, height, rgb) = if is_hdr {
+            let decoder = HdrDecoder::new(BufReader::new(File::open(filename)?))?;
+            let meta = decoder.metadata();
+            let rgb : Vec<[f32; 3]> = decoder.read_image_hdr()?.into_iter().map(|p| p.0).collect();
+            (meta.width as usize, meta.height as usize, rgb)
+        } else {
+            let img = image::open(filename)?.into_rgb32f();
+            (img.width() as usize, img.height() as usize, img.pixels().map(|p| p.0).collect())
+        };
+
+        let pixels = rgb.iter().map(|p| Color::new_arg(p[0] as f64, p[1] as f64, p[2] as f64)).collect();
+        Ok(EnvironmentMap::new(width, height, pixels, rotation, intensity))
+    }
+
+    pub fn new(width : usize, height : usize, pixels : Vec<Color>, rotation : f64, intensity : f64) -> Self{
+        // Sample pixels by luminance, weighted by sin(theta) for the area each row covers on the sphere.
+        let mut columns = Vec::with_capacity(height);
+        let mut row_weights = Vec::with_capacity(height);
+        for j in 0..height {
+            let sin_theta = (PI * (j as f64 + 0.5) / height as f64).sin();
+            let row : Vec<f64> = (0..width).map(|i| luminance(pixels[j * width + i]) * sin_theta).collect();
+            let dist = Distribution1D::new(row);
+            row_weights.push(dist.func_int);
+            columns.push(dist);
+        }
+
+        let radians = degrees_to_radians(rotation);
+        Self {
+            width,
+            height,
+            pixels,
+            intensity,
+            sin_rotation : radians.sin(),
+            cos_rotation : radians.cos(),
+            rows : Distribution1D::new(row_weights),
+            columns,
+        }
+    }
+
+    fn world_to_map(&self, d : &Rvec3) -> Rvec3{
+        // Undo the rotation about +y.
+        Rvec3::new_arg(
+            self.cos_rotation * d.e[0] - self.sin_rotation * d.e[2],
+            d.e[1],
+            self.sin_rotation * d.e[0] + self.cos_rotation * d.e[2],
+        )
+    }
+
+    fn map_to_world(&self, d : &Rvec3) -> Rvec3{
+        Rvec3::new_arg(
+            self.cos_rotation * d.e[0] + self.sin_rotation * d.e[2],
+            d.e[1],
+            -self.sin_rotation * d.e[0] + self.cos_rotation * d.e[2],
+        )
+    }
+
+    fn direction_uv(&self, direction : &Rvec3) -> (f64, f64){
+        // u runs around +y starting at -x, v from +y (0) down to -y (1).
+        let mut d = *direction;
+        let d = self.world_to_map(&Rvec3::unit_vector(&mut d));
+        let u = ((-d.e[2]).atan2(d.e[0]) + PI) / (2.0 * PI);
+        let v = d.e[1].clamp(-1.0, 1.0).acos() / PI;
+        (u, v)
+    }
+
+    fn pixel_index(&self, u : f64, v : f64) -> (usize, usize){
+        let i = ((u * self.width as f64) as usize).min(self.width - 1);
+        let j = ((v * self.height as f64) as usize).min(self.height - 1);
+        (i, j)
+    }
+}
+
+impl Environment for EnvironmentMap{
+    fn value(&self, direction : &Rvec3) -> Color {
+        let (u, v) = self.direction_uv(direction);
+        let (i, j) = self.pixel_index(u, v);
+        self.intensity * self.pixels[j * self.width + i]
+    }
+
+    fn is_sampled(&self) -> bool {
+        self.rows.func_int > 0.0
+    }
+
+    fn pdf_value(&self, direction : &Rvec3) -> f64 {
+        // Density over the image, divided by the Jacobian 2 pi^2 sin(theta) of the lat-long mapping.
+        let (u, v) = self.direction_uv(direction);
+        let sin_theta = (PI * v).sin();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        let (i, j) = self.pixel_index(u, v);
+        let pdf_uv = self.rows.pdf(j) * self.columns[j].pdf(i);
+        pdf_uv / (2.0 * PI * PI * sin_theta)
+    }
+
+    fn random(&self) -> Rvec3 {
+        let (r1, r2) = sample_2d();
+        let (v, j) = self.rows.sample(r1);
+        let (u, _) = self.columns[j].sample(r2);
+
+        let theta = PI * v;
+        let a = 2.0 * PI * u - PI;
+        let d = Rvec3::new_arg(theta.sin() * a.cos(), theta.cos(), -theta.sin() * a.sin());
+        self.map_to_world(&d)
+    }
+}
+
+// Puts an environment in a light list. It is infinitely far away, so it is never hit; misses
+// are shaded by the camera instead.
+pub struct EnvironmentLight{
+    environment : Arc<dyn Environment>,
+}
+
+impl EnvironmentLight{
+    pub fn new(environment : Arc<dyn Environment>) -> Self{
+        Self { environment }
+    }
+}
+
+impl Hittable for EnvironmentLight{
+    fn hit(&self, _ray : &mut Ray, _ray_t : &mut Interval, _rec : &mut HitRecord) -> bool {
+        false
+    }
+
+    fn bounding_box(&self) -> AABB {
+        AABB::new()
+    }
+
+    fn pdf_value(&self, _origin : &Point3, direction : &Rvec3) -> f64 {
+        self.environment.pdf_value(direction)
+    }
+
+    fn random(&self, _origin : &Point3) -> Rvec3 {
+        self.environment.random()
+    }
+}
@@ -0,0 +1,240 @@
+use crate::color::*;
+use crate::aov::AovType;
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+// First line of a checkpoint file, followed by the width and height.
+const CHECKPOINT_MAGIC : &str = "rtracer-film 2";
+const CHECKPOINT_PIXEL_BYTES : usize = 6 * 8 + 4;
+
+// HDR framebuffer. Pixels are the filter-weighted average of the samples splatted into them;
+// separately each pixel keeps count of the samples taken for it, and their luminance, to
+// estimate its noise. Extra passes (see aov.rs) are kept as layers, films of their own.
+pub struct Film{
+    width : i32,
+    height : i32,
+    radiance : Vec<Color>, // Filter-weighted sum of the samples splatted into each pixel
+    weights : Vec<f64>, // Sum of those filter weights
+    luminance_sum : Vec<f64>, // Sum of the luminance of the samples taken for each pixel
+    luminance_sq : Vec<f64>, // Sum of their squared luminance, for the variance
+    samples : Vec<u32>,    // Number of samples taken for each pixel
+    layers : Vec<(AovType, Film)>, // Extra passes; not part of checkpoints
+}
+
+impl Film{
+    pub fn new(width : i32, height : i32) -> Self{
+        let n = (width.max(0) * height.max(0)) as usize;
+        Self {
+            width,
+            height,
+            radiance : vec![Color::new(); n],
+            weights : vec![0.0; n],
+            luminance_sum : vec![0.0; n],
+            luminance_sq : vec![0.0; n],
+            samples : vec![0; n],
+            layers : Vec::new(),
+        }
+    }
+
+    pub fn width(&self) -> i32 { self.width }
+    pub fn height(&self) -> i32 { self.height }
+
+    fn index(&self, i : i32, j : i32) -> usize{
+        (j * self.width + i) as usize
+    }
+
+    pub fn add_sample(&mut self, i : i32, j : i32, color : Color){
+        // A sample that only counts for its own pixel, as with a box filter.
+        self.record_sample(i, j, color);
+        self.splat(i, j, color, 1.0);
+    }
+
+    pub fn record_sample(&mut self, i : i32, j : i32, color : Color){
+        // Count a sample taken for pixel i,j without adding it to the image.
+        let idx = self.index(i, j);
+        let y = luminance(color);
+        self.luminance_sum[idx] += y;
+        self.luminance_sq[idx] += y * y;
+        self.samples[idx] += 1;
+    }
+
+    pub fn splat(&mut self, i : i32, j : i32, color : Color, weight : f64){
+        let idx = self.index(i, j);
+        self.radiance[idx] += weight * color;
+        self.weights[idx] += weight;
+    }
+
+    pub fn add_layer(&mut self, aov : AovType){
+        if self.layer(aov).is_none() {
+            self.layers.push((aov, Film::new(self.width, self.height)));
+        }
+    }
+
+    pub fn layer(&self, aov : AovType) -> Option<&Film>{
+        self.layers.iter().find(|(a, _)| *a == aov).map(|(_, layer)| layer)
+    }
+
+    pub fn layer_mut(&mut self, aov : AovType) -> Option<&mut Film>{
+        self.layers.iter_mut().find(|(a, _)| *a == aov).map(|(_, layer)| layer)
+    }
+
+    pub fn layers(&self) -> &[(AovType, Film)]{
+        &self.layers
+    }
+
+    pub fn layers_mut(&mut self) -> &mut [(AovType, Film)]{
+        &mut self.layers
+    }
+
+    pub fn crop(&self, x0 : i32, y0 : i32, width : i32, height : i32) -> Film{
+        // Copy of the width x height block starting at x0,y0, samples and all, without layers.
+        let mut tile = Film::new(width, height);
+        for j in 0..height {
+            for i in 0..width {
+                let (src, dst) = (self.index(x0 + i, y0 + j), tile.index(i, j));
+                tile.copy_pixel(dst, self, src);
+            }
+        }
+        tile
+    }
+
+    pub fn add_tile(&mut self, x0 : i32, y0 : i32, tile : &Film){
+        // Add everything in `tile` to the block starting at x0,y0. The tile may stick out past
+        // the edges, e.g. with the margin a filter splats into; that part is dropped. ID layers
+        // only take pixels they don't have yet.
+        for (aov, tile_layer) in tile.layers.iter() {
+            if let Some(layer) = self.layer_mut(*aov) {
+                layer.merge_tile(x0, y0, tile_layer, aov.is_id());
+            }
+        }
+        self.merge_tile(x0, y0, tile, false);
+    }
+
+    fn merge_tile(&mut self, x0 : i32, y0 : i32, tile : &Film, only_empty : bool){
+        for j in 0..tile.height {
+            for i in 0..tile.width {
+                let (x, y) = (x0 + i, y0 + j);
+                if x < 0 || y < 0 || x >= self.width || y >= self.height {
+                    continue;
+                }
+                let (src, dst) = (tile.index(i, j), self.index(x, y));
+                if only_empty && self.samples[dst] > 0 {
+                    continue;
+                }
+                self.radiance[dst] += tile.radiance[src];
+                self.weights[dst] += tile.weights[src];
+                self.luminance_sum[dst] += tile.luminance_sum[src];
+                self.luminance_sq[dst] += tile.luminance_sq[src];
+                self.samples[dst] += tile.samples[src];
+            }
+        }
+    }
+
+    fn copy_pixel(&mut self, dst : usize, from : &Film, src : usize){
+        self.radiance[dst] = from.radiance[src];
+        self.weights[dst] = from.weights[src];
+        self.luminance_sum[dst] = from.luminance_sum[src];
+        self.luminance_sq[dst] = from.luminance_sq[src];
+        self.samples[dst] = from.samples[src];
+    }
+
+    pub fn sample_count(&self, i : i32, j : i32) -> u32{
+        self.samples[self.index(i, j)]
+    }
+
+    pub fn total_samples(&self) -> u64{
+        self.samples.iter().map(|&n| n as u64).sum()
+    }
+
+    pub fn pixel(&self, i : i32, j : i32) -> Color{
+        // Weighted mean radiance of the pixel, or black if nothing has landed in it yet.
+        let idx = self.index(i, j);
+        if self.weights[idx] == 0.0 {
+            return Color::new();
+        }
+        self.radiance[idx] / self.weights[idx]
+    }
+
+    pub fn set_pixel(&mut self, i : i32, j : i32, color : Color){
+        // Replace the pixel's value, keeping its weight.
+        let idx = self.index(i, j);
+        self.radiance[idx] = self.weights[idx] * color;
+    }
+
+    pub fn variance(&self, i : i32, j : i32) -> f64{
+        // Estimated variance of the pixel's mean luminance, infinite below two samples.
+        let idx = self.index(i, j);
+        let n = self.samples[idx] as f64;
+        if n < 2.0 {
+            return f64::INFINITY;
+        }
+        let mean = self.luminance_sum[idx] / n;
+        ((self.luminance_sq[idx] / n - mean * mean) * n / (n - 1.0)).max(0.0) / n
+    }
+
+    pub fn relative_error(&self, i : i32, j : i32) -> f64{
+        // Standard error of the pixel's mean luminance over the mean itself. Very dark pixels are
+        // measured against a floor so a few stray samples don't keep them sampling forever.
+        let n = self.sample_count(i, j) as f64;
+        if n < 2.0 {
+            return f64::INFINITY;
+        }
+        let mean = self.luminance_sum[self.index(i, j)] / n;
+        self.variance(i, j).sqrt() / mean.max(1e-3)
+    }
+
+    pub fn save(&self, path : &Path) -> io::Result<()>{
+        // Checkpoint of the raw sums, so a later render can keep adding samples. Written to a
+        // temporary file first so an interrupted save never clobbers the previous checkpoint.
+        let tmp = path.with_extension("tmp");
+        {
+            let mut out = BufWriter::new(File::create(&tmp)?);
+            writeln!(out, "{}\n{} {}", CHECKPOINT_MAGIC, self.width, self.height)?;
+            for idx in 0..self.samples.len() {
+                let values = [self.radiance[idx][0], self.radiance[idx][1], self.radiance[idx][2],
+                    self.weights[idx], self.luminance_sum[idx], self.luminance_sq[idx]];
+                for v in values {
+                    out.write_all(&v.to_le_bytes())?;
+                }
+                out.write_all(&self.samples[idx].to_le_bytes())?;
+            }
+            out.flush()?;
+        }
+        std::fs::rename(&tmp, path)
+    }
+
+    pub fn load(path : &Path) -> io::Result<Film>{
+        let mut data = Vec::new();
+        BufReader::new(File::open(path)?).read_to_end(&mut data)?;
+        let invalid = |msg : &str| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), msg));
+
+        // Two header lines, then the sums of each pixel.
+        let mut lines = data.splitn(3, |&b| b == b'\n');
+        if lines.next() != Some(CHECKPOINT_MAGIC.as_bytes()) {
+            return Err(invalid("not a checkpoint file"));
+        }
+        let size = lines.next().and_then(|l| std::str::from_utf8(l).ok()).ok_or_else(|| invalid("missing image size"))?;
+        let (width, height) = match size.split_once(' ').map(|(w, h)| (w.parse::<i32>(), h.parse::<i32>())) {
+            Some((Ok(w), Ok(h))) if w > 0 && h > 0 => (w, h),
+            _ => return Err(invalid("bad image size")),
+        };
+        let body = lines.next().unwrap_or_default();
+
+        let mut film = Film::new(width, height);
+        if body.len() != film.samples.len() * CHECKPOINT_PIXEL_BYTES {
+            return Err(invalid("truncated pixel data"));
+        }
+        let f64_at = |at : usize| f64::from_le_bytes(body[at..at + 8].try_into().unwrap());
+        for idx in 0..film.samples.len() {
+            let at = idx * CHECKPOINT_PIXEL_BYTES;
+            film.radiance[idx] = Color::new_arg(f64_at(at), f64_at(at + 8), f64_at(at + 16));
+            film.weights[idx] = f64_at(at + 24);
+            film.luminance_sum[idx] = f64_at(at + 32);
+            film.luminance_sq[idx] = f64_at(at + 40);
+            film.samples[idx] = u32::from_le_bytes(body[at + 48..at + 52].try_into().unwrap());
+        }
+        Ok(film)
+    }
+}
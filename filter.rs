@@ -0,0 +1,187 @@
+// Pixel reconstruction filters.
+//
+// Every camera sample is splatted into the pixels around it, weighted by the filter at its
+// offset from each pixel's center (in pixels); a pixel is the weighted average of what it got.
+// All filters here are separable, f(x, y) = f(x) f(y).
+
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+pub trait Filter : Send + Sync {
+    // Beyond this offset along either axis the weight is zero.
+    fn radius(&self) -> f64;
+
+    fn evaluate_1d(&self, x : f64) -> f64;
+
+    fn evaluate(&self, x : f64, y : f64) -> f64 {
+        self.evaluate_1d(x) * self.evaluate_1d(y)
+    }
+}
+
+// The filters that can be picked by name.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FilterType{
+    Box,
+    Tent,
+    Gaussian,
+    Mitchell,
+    Lanczos,
+}
+
+impl FilterType{
+    pub fn from_name(name : &str) -> Option<FilterType>{
+        match name {
+            "box" => Some(FilterType::Box),
+            "tent" => Some(FilterType::Tent),
+            "gaussian" => Some(FilterType::Gaussian),
+            "mitchell" => Some(FilterType::Mitchell),
+            "lanczos" => Some(FilterType::Lanczos),
+            _ => None,
+        }
+    }
+
+    pub fn default_radius(&self) -> f64{
+        match self {
+            FilterType::Box => 0.5,
+            FilterType::Tent => 1.0,
+            FilterType::Gaussian => 1.5,
+            FilterType::Mitchell | FilterType::Lanczos => 2.0,
+        }
+    }
+
+    pub fn build(&self, radius : Option<f64>) -> Arc<dyn Filter>{
+        let r = radius.unwrap_or(self.default_radius()).max(0.5);
+        match self {
+            FilterType::Box => Arc::new(BoxFilter::new(r)),
+            FilterType::Tent => Arc::new(TentFilter::new(r)),
+            FilterType::Gaussian => Arc::new(GaussianFilter::new(r, r / 3.0)),
+            FilterType::Mitchell => Arc::new(MitchellFilter::new(r, 1.0 / 3.0, 1.0 / 3.0)),
+            FilterType::Lanczos => Arc::new(LanczosFilter::new(r)),
+        }
+    }
+}
+
+// Equal weight inside the radius. With radius 0.5 each sample only counts for its own pixel,
+// which is what averaging the samples of a pixel does.
+pub struct BoxFilter{
+    radius : f64,
+}
+
+impl BoxFilter{
+    pub fn new(radius : f64) -> Self{
+        Self { radius }
+    }
+}
+
+impl Filter for BoxFilter{
+    fn radius(&self) -> f64 { self.radius }
+
+    fn evaluate_1d(&self, x : f64) -> f64 {
+        if x.abs() <= self.radius { 1.0 } else { 0.0 }
+    }
+}
+
+// Linear falloff from the center to zero at the radius.
+pub struct TentFilter{
+    radius : f64,
+}
+
+impl TentFilter{
+    pub fn new(radius : f64) -> Self{
+        Self { radius }
+    }
+}
+
+impl Filter for TentFilter{
+    fn radius(&self) -> f64 { self.radius }
+
+    fn evaluate_1d(&self, x : f64) -> f64 {
+        (self.radius - x.abs()).max(0.0)
+    }
+}
+
+// Gaussian of standard deviation `sigma`, shifted down so it reaches zero at the radius.
+pub struct GaussianFilter{
+    radius : f64,
+    sigma : f64,
+    edge : f64, // Value of the unshifted Gaussian at the radius
+}
+
+impl GaussianFilter{
+    pub fn new(radius : f64, sigma : f64) -> Self{
+        let mut f = Self { radius, sigma, edge : 0.0 };
+        f.edge = f.gaussian(radius);
+        f
+    }
+
+    fn gaussian(&self, x : f64) -> f64{
+        (-x * x / (2.0 * self.sigma * self.sigma)).exp()
+    }
+}
+
+impl Filter for GaussianFilter{
+    fn radius(&self) -> f64 { self.radius }
+
+    fn evaluate_1d(&self, x : f64) -> f64 {
+        (self.gaussian(x) - self.edge).max(0.0)
+    }
+}
+
+// Mitchell-Netravali cubic, stretched over the radius. B = C = 1/3 balances blurring against
+// ringing; the small negative lobes sharpen edges.
+pub struct MitchellFilter{
+    radius : f64,
+    b : f64,
+    c : f64,
+}
+
+impl MitchellFilter{
+    pub fn new(radius : f64, b : f64, c : f64) -> Self{
+        Self { radius, b, c }
+    }
+}
+
+impl Filter for MitchellFilter{
+    fn radius(&self) -> f64 { self.radius }
+
+    fn evaluate_1d(&self, x : f64) -> f64 {
+        // The cubic is defined on [-2, 2].
+        let x = (2.0 * x / self.radius).abs();
+        let (b, c) = (self.b, self.c);
+        let value = if x < 1.0 {
+            (12.0 - 9.0 * b - 6.0 * c) * x * x * x + (-18.0 + 12.0 * b + 6.0 * c) * x * x + (6.0 - 2.0 * b)
+        } else if x < 2.0 {
+            (-b - 6.0 * c) * x * x * x + (6.0 * b + 30.0 * c) * x * x + (-12.0 * b - 48.0 * c) * x + (8.0 * b + 24.0 * c)
+        } else {
+            0.0
+        };
+        value / 6.0
+    }
+}
+
+// Sinc windowed by a wider sinc that reaches zero at the radius; the sharpest of these, with
+// the most ringing.
+pub struct LanczosFilter{
+    radius : f64,
+}
+
+impl LanczosFilter{
+    pub fn new(radius : f64) -> Self{
+        Self { radius }
+    }
+}
+
+fn sinc(x : f64) -> f64{
+    if x.abs() < 1e-5 { 1.0 } else { (PI * x).sin() / (PI * x) }
+}
+
+impl Filter for LanczosFilter{
+    fn radius(&self) -> f64 { self.radius }
+
+    fn evaluate_1d(&self, x : f64) -> f64 {
+        if x.abs() > self.radius {
+            return 0.0;
+        }
+        sinc(x) * sinc(x / self.radius)
+    }
+}
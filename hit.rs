@@ -1,29 +1,43 @@
 
 
 use crate::rvec3::*;
-use crate::Ray;
+use crate::ray::Ray;
 use crate::interval::*;
 use crate::material::*;
 use crate::texture::Texture;
 use crate::utility::INFINITY;
-use crate::utility::degrees_to_radians;
+use crate::mat4::Mat4;
 use crate::utility::random_double;
-use std::borrow::BorrowMut;
 
 
-use std::rc::Rc;
+use std::cell::Cell;
+use std::sync::Arc;
 use crate::color::*;
-use std::cell::RefCell;
 use crate::aabb::AABB;
 
+thread_local! {
+    // Set while hit_surface traces a ray that participating media should let through.
+    static SKIP_MEDIA : Cell<bool> = const { Cell::new(false) };
+}
+
+pub fn hit_surface(world : &dyn Hittable, r : &mut Ray, ray_t : &mut Interval, rec : &mut HitRecord) -> bool{
+    // Like world.hit, but finds the first surface along the ray, as if every medium were empty.
+    SKIP_MEDIA.with(|skip| skip.set(true));
+    let hit = world.hit(r, ray_t, rec);
+    SKIP_MEDIA.with(|skip| skip.set(false));
+    hit
+}
+
 pub struct HitRecord{
     pub p : Point3,
     pub normal : Rvec3,
     pub t : f64,
     pub front_face : bool,
-    pub mat : Rc<RefCell<dyn Material>>,
+    pub mat : Arc<dyn Material>,
     pub v : f64, // coord mappings
     pub u : f64,
+    pub object_id : u32, // Set by a list tagged with number_objects, 0 for untagged objects
+    pub medium : bool, // A scattering event inside a participating medium rather than a surface
 }
 
 impl HitRecord{
@@ -33,9 +47,11 @@ impl HitRecord{
             normal : Rvec3::new(),
             t : 0.0,
             front_face : false,
-            mat : Rc::new(RefCell::new(Lambertian::new(Color::new_arg(251.0,0.0,120.0)))),
+            mat : Arc::new(Lambertian::new(Color::new_arg(251.0,0.0,120.0))),
             v : 0.0,
             u : 0.0,
+            object_id : 0,
+            medium : false,
         }
     }
 
@@ -58,77 +74,51 @@ impl Default for HitRecord{
     }       
 }
 
-pub trait Hittable{
-    fn hit(&mut self, ray: &mut Ray, ray_t : &mut Interval, rec: &mut HitRecord) -> bool;
+pub trait Hittable : Send + Sync {
+    fn hit(&self, ray: &mut Ray, ray_t : &mut Interval, rec: &mut HitRecord) -> bool;
     fn bounding_box(&self) -> AABB;
-}
 
-pub struct Translate{
-    object : Rc<RefCell<dyn Hittable>>,
-    offset : Rvec3,
-    bbox : AABB,    
-}
-
-
-impl Translate{
-    pub fn new(p : Rc<RefCell<dyn Hittable>>, displacement : Rvec3) -> Self{
-        Self { 
-            object : p.clone(),
-            offset : displacement,
-            bbox : p.borrow().bounding_box() + displacement
-        }
+    // Solid-angle density of `random` picking `direction` from `origin`. Only objects that can
+    // be sampled as lights implement this and `random`.
+    fn pdf_value(&self, _origin : &Point3, _direction : &Rvec3) -> f64 {
+        0.0
     }
-}
 
-impl Hittable for Translate{
-    fn bounding_box(&self) -> AABB {
-        self.bbox
+    // Random direction from `origin` towards the object.
+    fn random(&self, _origin : &Point3) -> Rvec3 {
+        Rvec3::new_arg(1.0, 0.0, 0.0)
     }
-
-    fn hit(&mut self, r: &mut Ray, ray_t : &mut Interval, rec: &mut HitRecord) -> bool {
-        // Move the ray backwards by the offset
-        let mut offset_r = Ray::new_time(r.origin() - self.offset, r.direction(), r.time());
-        
-        // Determine where (if any) an intersection occurs along the offset ray
-        if !self.object.as_ref().borrow_mut().hit(&mut offset_r, ray_t, rec){
-            return false;
-        }
-
-        // Move the intersection point forwards by the offset
-        rec.p += self.offset;
-
-        true        
-    }   
 }
 
-pub struct RotateY{
-    object : Rc<RefCell<dyn Hittable>>,
-    sin_theta : f64,
-    cos_theta : f64,
+// Places an object in the world with an affine matrix. Rays are taken into object space with
+// the inverse, hits brought back with the matrix, and normals with the inverse transpose so
+// they stay perpendicular under non-uniform scale.
+pub struct Transform{
+    object : Arc<dyn Hittable>,
+    matrix : Mat4,  // Object space to world space
+    inverse : Mat4, // World space to object space
+    normal_matrix : Mat4, // Inverse transpose, for normals
+    det : f64, // |determinant| of the linear part, for light sampling densities
     bbox : AABB,
 }
 
-impl RotateY{
-    pub fn new(p : Rc<RefCell<dyn Hittable>>, angle : f64) -> Self{
-        let radians = degrees_to_radians(angle);
-        let _sin_theta = radians.sin();
-        let _cos_theta = radians.cos();
-        let mut _bbox = p.as_ref().borrow_mut().bounding_box();
+impl Transform{
+    pub fn new(p : Arc<dyn Hittable>, matrix : Mat4) -> Self{
+        let inverse = matrix.inverse().expect("transform matrix is not invertible");
+        let object_box = p.bounding_box();
 
+        // The world box bounds the eight transformed corners of the object's box.
         let mut min = Point3::new_arg( INFINITY, INFINITY, INFINITY);
         let mut max = Point3::new_arg(-INFINITY,-INFINITY,-INFINITY);
 
         for i in 0..2{
             for j in 0..2{
                 for k in 0..2{
-                    let x = (i as f64)*_bbox.x.max + (1.0 - i as f64) * _bbox.x.min;
-                    let y = (j as f64)*_bbox.y.max + (1.0 - j as f64) * _bbox.y.min;
-                    let z = (k as f64)*_bbox.z.max + (1.0 - k as f64) * _bbox.z.min;
-                    
-                    let newx = _cos_theta * x + _sin_theta * z;
-                    let newz = -_sin_theta * x + _cos_theta *z;
+                    let x = if i == 1 { object_box.x.max } else { object_box.x.min };
+                    let y = if j == 1 { object_box.y.max } else { object_box.y.min };
+                    let z = if k == 1 { object_box.z.max } else { object_box.z.min };
 
-                    let tester = Rvec3::new_arg(newx, y, newz);
+                    let tester = matrix.transform_point(Point3::new_arg(x, y, z));
 
                     for c in 0..3{
                         min[c] = min[c].min(tester[c]);
@@ -138,86 +128,112 @@ impl RotateY{
             }
         }
 
+        Self { object : p, matrix, inverse, normal_matrix : inverse.transpose(), det : matrix.determinant().abs(), bbox : AABB::new_points(min, max) }
+    }
 
-        _bbox = AABB::new_points(min, max);
-        Self { object: p, sin_theta: _sin_theta, cos_theta: _cos_theta, bbox: _bbox}
+    pub fn translate(p : Arc<dyn Hittable>, displacement : Rvec3) -> Self{
+        Transform::new(p, Mat4::translate(displacement))
     }
-                                    
-}
 
-impl Hittable for RotateY{
-    fn bounding_box(&self) -> AABB {
-        self.bbox
+    pub fn rotate(p : Arc<dyn Hittable>, axis : Rvec3, angle : f64) -> Self{
+        Transform::new(p, Mat4::rotate(axis, angle))
     }
 
-    fn hit(&mut self, r: &mut Ray, ray_t : &mut Interval, rec: &mut HitRecord) -> bool {
-        // Change the ray from world space to object space
-        let mut origin = r.origin();
-        let mut direction = r.direction();
+    pub fn rotate_y(p : Arc<dyn Hittable>, angle : f64) -> Self{
+        Transform::new(p, Mat4::rotate_y(angle))
+    }
 
-        origin[0] = self.cos_theta * r.origin()[0] - self.sin_theta * r.origin()[2];
-        origin[2] = self.sin_theta * r.origin()[0] + self.cos_theta * r.origin()[2];
+    pub fn scale(p : Arc<dyn Hittable>, factors : Rvec3) -> Self{
+        Transform::new(p, Mat4::scale(factors))
+    }
+}
 
-        direction[0] = self.cos_theta * r.direction()[0] - self.sin_theta * r.direction()[2];
-        direction[2] = self.sin_theta * r.direction()[0] + self.cos_theta * r.direction()[2];
+impl Hittable for Transform{
+    fn bounding_box(&self) -> AABB {
+        self.bbox
+    }
 
-        let mut rotated_r = Ray::new_time(origin,direction,r.time());
+    fn hit(&self, r: &mut Ray, ray_t : &mut Interval, rec: &mut HitRecord) -> bool {
+        // Change the ray from world space to object space. Rays keep unit directions, so
+        // distances along the object ray are `scale` times those along the world ray.
+        let mut direction = self.inverse.transform_vector(r.direction());
+        let scale = direction.length();
+        if scale == 0.0 {
+            return false;
+        }
+        let mut object_r = Ray::new_time(self.inverse.transform_point(r.origin()), direction, r.time());
+        let mut object_t = Interval::new_arg(ray_t.min * scale, ray_t.max * scale);
 
         // Determine where (if any) an intersection occurs in object space
-        if !self.object.as_ref().borrow_mut().hit(&mut rotated_r, ray_t,rec){
+        if !self.object.hit(&mut object_r, &mut object_t, rec){
             return false;
-        } 
-        
-        // Change the intersection point from object space to world space
-        let mut p = rec.p;
-        p[0] =  self.cos_theta * rec.p[0] + self.sin_theta * rec.p[2];
-        p[2] = -self.sin_theta * rec.p[0] + self.cos_theta * rec.p[2];
+        }
 
+        // Change the intersection from object space to world space
+        rec.t /= scale;
+        rec.p = self.matrix.transform_point(rec.p);
 
-        // Change the normal from object space to world space
-        let mut normal = rec.normal;
-        normal[0] =  self.cos_theta * rec.normal[0] + self.sin_theta * rec.normal[2];
-        normal[2] = -self.sin_theta * rec.normal[0] + self.cos_theta * rec.normal[2];
+        // The inverse transpose keeps the normal on the side the ray came from.
+        let mut normal = self.normal_matrix.transform_vector(rec.normal);
+        rec.normal = Rvec3::unit_vector(&mut normal);
 
+        true
+    }
 
-        rec.p = p;
-        rec.normal = normal;
+    fn pdf_value(&self, origin : &Point3, direction : &Rvec3) -> f64 {
+        // A density over object-space directions becomes one over world directions through the
+        // solid-angle Jacobian |det A| / |A d|^3 of the linear part A, with d a unit vector.
+        let mut object_dir = self.inverse.transform_vector(*direction);
+        if object_dir.near_zero() {
+            return 0.0;
+        }
+        let object_dir = Rvec3::unit_vector(&mut object_dir);
+        let pdf = self.object.pdf_value(&self.inverse.transform_point(*origin), &object_dir);
+        let stretch = self.matrix.transform_vector(object_dir).length();
+        pdf * stretch * stretch * stretch / self.det
+    }
 
-        true
+    fn random(&self, origin : &Point3) -> Rvec3 {
+        let direction = self.object.random(&self.inverse.transform_point(*origin));
+        self.matrix.transform_vector(direction)
     }
 }
 
 
 pub struct ConstantMedium{
-    boundary : Rc<RefCell<dyn Hittable>>,
+    boundary : Arc<dyn Hittable>,
     neg_inv_density : f64,
-    phase_function : Rc<RefCell<dyn Material>>,
+    phase_function : Arc<dyn Material>,
 }
 
 impl ConstantMedium{
-    pub fn new_tex(b : Rc<RefCell<dyn Hittable>>, d : f64, a : Rc<dyn Texture>) -> Self{
-        Self { boundary: b, neg_inv_density: -1.0/d, phase_function: Rc::new(RefCell::new(Isotropic::new_tex(a))) }
+    pub fn new_tex(b : Arc<dyn Hittable>, d : f64, a : Arc<dyn Texture>) -> Self{
+        Self { boundary: b, neg_inv_density: -1.0/d, phase_function: Arc::new(Isotropic::new_tex(a)) }
     }
-    pub fn new_col(b : Rc<RefCell<dyn Hittable>>, d : f64, c : Color) -> Self{
-        Self { boundary: b, neg_inv_density: -1.0/d, phase_function: Rc::new(RefCell::new(Isotropic::new(c))) }
+    pub fn new_col(b : Arc<dyn Hittable>, d : f64, c : Color) -> Self{
+        Self { boundary: b, neg_inv_density: -1.0/d, phase_function: Arc::new(Isotropic::new(c)) }
     }
 }
 
 impl Hittable for ConstantMedium{
     fn bounding_box(&self) -> AABB {
-        self.boundary.borrow().bounding_box()        
+        self.boundary.bounding_box()        
     }
 
-    fn hit(&mut self, r: &mut Ray, ray_t : &mut Interval, rec: &mut HitRecord) -> bool {
-        // Print occasional samples when debugging. To enable, set enableDebug true.
-        let enableDebug = false;
-        let debugging = enableDebug && random_double() < 0.00001;
+    fn hit(&self, r: &mut Ray, ray_t : &mut Interval, rec: &mut HitRecord) -> bool {
+        if SKIP_MEDIA.with(Cell::get) {
+            return false;
+        }
+
+        // Print occasional samples when debugging. To enable, set enable_debug true.
+        let enable_debug = false;
+        let debugging = enable_debug && random_double() < 0.00001;
 
         let mut rec1 = HitRecord::new();
         let mut rec2 = HitRecord::new();
 
-        if !self.boundary.as_ref().borrow_mut().hit(r, &mut Interval::UNIVERSE, &mut rec1) { return false;} 
-        if !self.boundary.as_ref().borrow_mut().hit(r, &mut Interval::new_arg(rec1.t + 0.0001, INFINITY), &mut rec2) {return false;}
+        if !self.boundary.hit(r, &mut Interval::new_arg(-INFINITY, INFINITY), &mut rec1) { return false;} 
+        if !self.boundary.hit(r, &mut Interval::new_arg(rec1.t + 0.0001, INFINITY), &mut rec2) {return false;}
 
         if debugging {
             eprintln!("ray_tmin={}, ray_tmax={}", &rec1.t,&rec2.t);
@@ -231,6 +247,8 @@ impl Hittable for ConstantMedium{
 
         let ray_length = r.direction().length();
         let distance_inside_boundary = (rec2.t - rec1.t) * ray_length;
+        // Drawn from the sample's independent stream rather than the sampler: a path can cross any
+        // number of media, which would run past the dimensions reserved for its bounce.
         let hit_distance = self.neg_inv_density * random_double().log10();
 
         if hit_distance > distance_inside_boundary {return false;}
@@ -242,9 +260,10 @@ impl Hittable for ConstantMedium{
             eprintln!("hit_distance= {} \nrec.t = {} \n rec.p = {} ", &hit_distance, &rec.t, &rec.p);
         }
 
-        rec.normal = Rvec3::new_arg(1.0, 0.0, 0.0); // arbitrary
+        rec.normal = Rvec3::unit_vector(&mut -r.direction()); // arbitrary, so facing back along the ray for the normal pass
         rec.front_face = true; //also arbitrary
-        rec.mat = Rc::clone(&self.phase_function);
+        rec.mat = Arc::clone(&self.phase_function);
+        rec.medium = true;
 
         true
     }
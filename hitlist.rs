@@ -2,39 +2,63 @@ use crate::hit::*;
 use crate::material::Material;
 use crate::ray::*;
 use crate::interval::*;
-use std::rc::Rc;
-use std::cell::RefCell;
+use std::sync::Arc;
 use crate::rvec3::*;
 use crate::quad::*;
 use crate::aabb::*;
+use crate::sampler::sample_1d;
 
 pub struct HittableList {
-    pub objects : Vec<Rc<RefCell<dyn Hittable>>> 
+    pub objects : Vec<Arc<dyn Hittable>>,
+    bbox : AABB,
+    ids : Vec<u32>, // Object ID of each object, for the object ID pass; empty for untagged lists
 }
 
 impl HittableList{
     pub fn new() -> Self{
         Self{
-            objects : Vec::new()
+            objects : Vec::new(),
+            bbox : AABB::new(),
+            ids : Vec::new(),
         }
     }
-    pub fn new_arg(obj : Vec<Rc<RefCell<dyn Hittable>>> ) -> Self{
+    pub fn new_arg(obj : Vec<Arc<dyn Hittable>> ) -> Self{
+        let mut bbox = AABB::new();
+        for object in obj.iter() {
+            bbox = AABB::new_boxes(bbox, object.bounding_box());
+        }
         Self { 
-            objects: obj 
+            objects: obj,
+            bbox,
+            ids : Vec::new(),
         }
     }
 
     pub fn clear(&mut self){
         self.objects.clear();
+        self.bbox = AABB::new();
+        self.ids.clear();
     }
 
-    pub fn add(&mut self, object : Rc<RefCell<dyn Hittable>>){
+    pub fn add(&mut self, object : Arc<dyn Hittable>){
+        self.bbox = AABB::new_boxes(self.bbox, object.bounding_box());
         self.objects.push(object);
     }
 
-    pub fn box_new(a : &mut Point3, b : &mut Point3, mat : Rc<RefCell<dyn Material>>) -> Rc<RefCell<HittableList>> {
+    pub fn number_objects(&mut self){
+        // Tag each object with its place in the list, counting from 1, for the object ID pass.
+        // Hits on an object get its ID from the list (or a LinearBvh built from it) as they are
+        // found, so the tags cost nothing when the pass isn't recorded.
+        self.ids = (1..=self.objects.len() as u32).collect();
+    }
+
+    pub fn ids(&self) -> &[u32]{
+        &self.ids
+    }
+
+    pub fn box_new(a : &mut Point3, b : &mut Point3, mat : Arc<dyn Material>) -> Arc<HittableList> {
         // Returns the 3D box (six sides) that contains the two opposite vertices a & b.
-        let sides = Rc::new(RefCell::new(HittableList::new()));
+        let mut sides = HittableList::new();
 
         // Construct the two opposite vertices with the minimum and maximum coordinates.
         let mut min = Point3::new_arg(a.x().min(b.x()), a.y().min(b.y()), a.z().min(b.z()));
@@ -44,14 +68,14 @@ impl HittableList{
         let dy = Rvec3::new_arg(0.0, max.y() - min.y(), 0.0);
         let dz = Rvec3::new_arg(0.0, 0.0, max.z() - min.z());
 
-        sides.borrow_mut().add(Rc::new(RefCell::new(Quad::new(Point3::new_arg(min.x(), min.y(), max.z()),  dx,  dy, mat.clone())))); // front    
-        sides.borrow_mut().add(Rc::new(RefCell::new(Quad::new(Point3::new_arg(max.x(), min.y(), max.z()), -dz,  dy, mat.clone())))); // right
-        sides.borrow_mut().add(Rc::new(RefCell::new(Quad::new(Point3::new_arg(max.x(), min.y(), min.z()), -dx,  dy, mat.clone())))); // back    
-        sides.borrow_mut().add(Rc::new(RefCell::new(Quad::new(Point3::new_arg(min.x(), min.y(), min.z()),  dz,  dy, mat.clone())))); // left    
-        sides.borrow_mut().add(Rc::new(RefCell::new(Quad::new(Point3::new_arg(min.x(), max.y(), max.z()),  dx, -dz, mat.clone())))); // top    
-        sides.borrow_mut().add(Rc::new(RefCell::new(Quad::new(Point3::new_arg(min.x(), min.y(), min.z()),  dx,  dz, mat.clone())))); // bottom    
+        sides.add(Arc::new(Quad::new(Point3::new_arg(min.x(), min.y(), max.z()),  dx,  dy, mat.clone()))); // front    
+        sides.add(Arc::new(Quad::new(Point3::new_arg(max.x(), min.y(), max.z()), -dz,  dy, mat.clone()))); // right
+        sides.add(Arc::new(Quad::new(Point3::new_arg(max.x(), min.y(), min.z()), -dx,  dy, mat.clone()))); // back    
+        sides.add(Arc::new(Quad::new(Point3::new_arg(min.x(), min.y(), min.z()),  dz,  dy, mat.clone()))); // left    
+        sides.add(Arc::new(Quad::new(Point3::new_arg(min.x(), max.y(), max.z()),  dx, -dz, mat.clone()))); // top    
+        sides.add(Arc::new(Quad::new(Point3::new_arg(min.x(), min.y(), min.z()),  dx,  dz, mat.clone()))); // bottom    
 
-        sides
+        Arc::new(sides)
     }
 }
 
@@ -62,15 +86,18 @@ impl Default for HittableList {
 }
 
 impl Hittable for HittableList{
-    fn hit(&mut self, ray: &mut Ray, ray_t : &mut Interval, rec: &mut HitRecord) -> bool {
+    fn hit(&self, ray: &mut Ray, ray_t : &mut Interval, rec: &mut HitRecord) -> bool {
         let mut hit_anything  = false;
         let mut closest_so_far = ray_t.max;
     
-        for object in self.objects.iter(){
+        for (i, object) in self.objects.iter().enumerate(){
             let mut temp_rec : HitRecord = HitRecord::new();
-            if object.borrow_mut().hit(ray, &mut Interval{min : ray_t.min, max : closest_so_far} ,&mut temp_rec){
+            if object.hit(ray, &mut Interval{min : ray_t.min, max : closest_so_far} ,&mut temp_rec){
                 hit_anything = true;
                 closest_so_far = temp_rec.t;
+                if let Some(&id) = self.ids.get(i) {
+                    temp_rec.object_id = id;
+                }
                 *rec = temp_rec;
             }
         }
@@ -78,6 +105,17 @@ impl Hittable for HittableList{
         hit_anything
     }
     fn bounding_box(&self) -> crate::aabb::AABB {
-        AABB::new()       
+        self.bbox
+    }
+
+    fn pdf_value(&self, origin : &Point3, direction : &Rvec3) -> f64 {
+        // Each object is picked with equal probability.
+        let weight = 1.0 / self.objects.len() as f64;
+        self.objects.iter().map(|object| weight * object.pdf_value(origin, direction)).sum()
+    }
+
+    fn random(&self, origin : &Point3) -> Rvec3 {
+        let n = self.objects.len();
+        self.objects[((sample_1d() * n as f64) as usize).min(n - 1)].random(origin)
     }
 }
\ No newline at end of file
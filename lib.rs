@@ -0,0 +1,32 @@
+pub mod rvec3;
+pub mod color;
+pub mod ray;
+pub mod hit;
+pub mod sphere;
+pub mod hitlist;
+pub mod utility;
+pub mod interval;
+pub mod camera;
+pub mod material;
+pub mod onb;
+pub mod sampler;
+pub mod pdf;
+pub mod light;
+pub mod environment;
+pub mod sky;
+pub mod aabb;
+pub mod mat4;
+pub mod bvh;
+pub mod texture;
+pub mod perlin;
+pub mod quad;
+pub mod triangle;
+pub mod obj;
+pub mod filter;
+pub mod film;
+pub mod aov;
+pub mod denoise;
+pub mod tonemap;
+pub mod output;
+pub mod scenes;
+pub mod scenefile;
@@ -0,0 +1,130 @@
+// Delta lights: point, spot and directional emitters with no geometry of their own.
+//
+// A ray can never hit them, so the camera reaches them only through shadow rays. Each
+// light returns the single direction it arrives from at a point, with its radiance already
+// divided by the (zero) solid angle, i.e. the irradiance it delivers normal to that direction.
+
+use crate::rvec3::*;
+use crate::color::*;
+use crate::utility::{degrees_to_radians, INFINITY};
+
+// Light arriving at a shading point from a delta light.
+pub struct LightSample{
+    pub direction : Rvec3, // Unit vector from the point towards the light
+    pub distance : f64, // How far a shadow ray has to reach, INFINITY for distant lights
+    pub irradiance : Color,
+}
+
+pub trait Light : Send + Sync {
+    // None when the light cannot reach `p` at all, e.g. outside a spot's cone.
+    fn sample(&self, p : &Point3) -> Option<LightSample>;
+}
+
+// Emits `intensity` (power per solid angle) equally in every direction.
+pub struct PointLight{
+    position : Point3,
+    intensity : Color,
+}
+
+impl PointLight{
+    pub fn new(position : Point3, intensity : Color) -> Self{
+        Self { position, intensity }
+    }
+}
+
+impl Light for PointLight{
+    fn sample(&self, p : &Point3) -> Option<LightSample> {
+        let mut to_light = self.position - *p;
+        let distance_squared = to_light.length_squared();
+        if distance_squared == 0.0 {
+            return None;
+        }
+        Some(LightSample{
+            direction : Rvec3::unit_vector(&mut to_light),
+            distance : distance_squared.sqrt(),
+            irradiance : self.intensity / distance_squared,
+        })
+    }
+}
+
+// A point light limited to a cone around `direction`. Full intensity inside `inner_angle`,
+// fading to zero at `outer_angle` (both half-angles in degrees); `falloff` is the exponent of
+// the fade, 1 being linear in the cosine.
+pub struct SpotLight{
+    position : Point3,
+    direction : Rvec3,
+    intensity : Color,
+    cos_inner : f64,
+    cos_outer : f64,
+    falloff : f64,
+}
+
+impl SpotLight{
+    pub fn new(position : Point3, direction : Rvec3, intensity : Color, inner_angle : f64, outer_angle : f64, falloff : f64) -> Self{
+        let mut d = direction;
+        let outer = outer_angle.clamp(0.0, 180.0);
+        let inner = inner_angle.clamp(0.0, outer);
+        Self {
+            position,
+            direction : Rvec3::unit_vector(&mut d),
+            intensity,
+            cos_inner : degrees_to_radians(inner).cos(),
+            cos_outer : degrees_to_radians(outer).cos(),
+            falloff,
+        }
+    }
+
+    fn cone(&self, cos_theta : f64) -> f64{
+        if cos_theta >= self.cos_inner {
+            return 1.0;
+        }
+        if cos_theta <= self.cos_outer {
+            return 0.0;
+        }
+        ((cos_theta - self.cos_outer) / (self.cos_inner - self.cos_outer)).powf(self.falloff)
+    }
+}
+
+impl Light for SpotLight{
+    fn sample(&self, p : &Point3) -> Option<LightSample> {
+        let mut to_light = self.position - *p;
+        let distance_squared = to_light.length_squared();
+        if distance_squared == 0.0 {
+            return None;
+        }
+        let direction = Rvec3::unit_vector(&mut to_light);
+        let cone = self.cone(Rvec3::dot(&-direction, &self.direction));
+        if cone <= 0.0 {
+            return None;
+        }
+        Some(LightSample{
+            direction,
+            distance : distance_squared.sqrt(),
+            irradiance : (cone / distance_squared) * self.intensity,
+        })
+    }
+}
+
+// Parallel light from infinitely far away, travelling along `direction`, like a sun.
+// `irradiance` is what it delivers to a surface facing it.
+pub struct DirectionalLight{
+    to_light : Rvec3,
+    irradiance : Color,
+}
+
+impl DirectionalLight{
+    pub fn new(direction : Rvec3, irradiance : Color) -> Self{
+        let mut d = -direction;
+        Self { to_light : Rvec3::unit_vector(&mut d), irradiance }
+    }
+}
+
+impl Light for DirectionalLight{
+    fn sample(&self, _p : &Point3) -> Option<LightSample> {
+        Some(LightSample{
+            direction : self.to_light,
+            distance : INFINITY,
+            irradiance : self.irradiance,
+        })
+    }
+}
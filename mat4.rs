@@ -0,0 +1,152 @@
+use std::ops::*;
+use crate::rvec3::*;
+use crate::utility::degrees_to_radians;
+
+// Row-major 4x4 matrix for affine transforms; points are column vectors, so A * B applies B first.
+#[derive(Copy, Clone)]
+pub struct Mat4{
+    pub m : [[f64; 4]; 4],
+}
+
+impl Mat4{
+    pub fn new(m : [[f64; 4]; 4]) -> Mat4{
+        Mat4 { m }
+    }
+
+    pub fn identity() -> Mat4{
+        Mat4 { m : [
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ]}
+    }
+
+    pub fn translate(offset : Rvec3) -> Mat4{
+        let mut r = Mat4::identity();
+        r.m[0][3] = offset.e[0];
+        r.m[1][3] = offset.e[1];
+        r.m[2][3] = offset.e[2];
+        r
+    }
+
+    pub fn scale(factors : Rvec3) -> Mat4{
+        let mut r = Mat4::identity();
+        r.m[0][0] = factors.e[0];
+        r.m[1][1] = factors.e[1];
+        r.m[2][2] = factors.e[2];
+        r
+    }
+
+    pub fn rotate(axis : Rvec3, angle : f64) -> Mat4{
+        // Counter-clockwise rotation by `angle` degrees about `axis` (Rodrigues' formula).
+        let mut a = axis;
+        let a = Rvec3::unit_vector(&mut a);
+        let radians = degrees_to_radians(angle);
+        let (s, c) = radians.sin_cos();
+        let t = 1.0 - c;
+        let (x, y, z) = (a.e[0], a.e[1], a.e[2]);
+        Mat4 { m : [
+            [t*x*x + c,   t*x*y - s*z, t*x*z + s*y, 0.0],
+            [t*x*y + s*z, t*y*y + c,   t*y*z - s*x, 0.0],
+            [t*x*z - s*y, t*y*z + s*x, t*z*z + c,   0.0],
+            [0.0,         0.0,         0.0,         1.0],
+        ]}
+    }
+
+    pub fn rotate_x(angle : f64) -> Mat4 { Mat4::rotate(Rvec3::new_arg(1.0, 0.0, 0.0), angle) }
+    pub fn rotate_y(angle : f64) -> Mat4 { Mat4::rotate(Rvec3::new_arg(0.0, 1.0, 0.0), angle) }
+    pub fn rotate_z(angle : f64) -> Mat4 { Mat4::rotate(Rvec3::new_arg(0.0, 0.0, 1.0), angle) }
+
+    pub fn transpose(&self) -> Mat4{
+        let mut r = Mat4::identity();
+        for i in 0..4 {
+            for j in 0..4 {
+                r.m[i][j] = self.m[j][i];
+            }
+        }
+        r
+    }
+
+    pub fn determinant(&self) -> f64{
+        // Of the upper-left 3x3 linear part; the bottom row of an affine matrix is 0 0 0 1.
+        let m = &self.m;
+        m[0][0] * (m[1][1]*m[2][2] - m[1][2]*m[2][1])
+            - m[0][1] * (m[1][0]*m[2][2] - m[1][2]*m[2][0])
+            + m[0][2] * (m[1][0]*m[2][1] - m[1][1]*m[2][0])
+    }
+
+    pub fn inverse(&self) -> Option<Mat4>{
+        // Gauss-Jordan elimination with partial pivoting; None if the matrix is singular.
+        let mut a = self.m;
+        let mut inv = Mat4::identity().m;
+
+        for col in 0..4 {
+            let pivot = (col..4).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs())).unwrap();
+            if a[pivot][col].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+
+            let p = a[col][col];
+            for j in 0..4 {
+                a[col][j] /= p;
+                inv[col][j] /= p;
+            }
+
+            for row in 0..4 {
+                if row != col {
+                    let f = a[row][col];
+                    for j in 0..4 {
+                        a[row][j] -= f * a[col][j];
+                        inv[row][j] -= f * inv[col][j];
+                    }
+                }
+            }
+        }
+
+        Some(Mat4 { m : inv })
+    }
+
+    pub fn transform_point(&self, p : Point3) -> Point3{
+        let m = &self.m;
+        Point3::new_arg(
+            m[0][0]*p.e[0] + m[0][1]*p.e[1] + m[0][2]*p.e[2] + m[0][3],
+            m[1][0]*p.e[0] + m[1][1]*p.e[1] + m[1][2]*p.e[2] + m[1][3],
+            m[2][0]*p.e[0] + m[2][1]*p.e[1] + m[2][2]*p.e[2] + m[2][3],
+        )
+    }
+
+    pub fn transform_vector(&self, v : Rvec3) -> Rvec3{
+        // Like transform_point, but ignores the translation.
+        let m = &self.m;
+        Rvec3::new_arg(
+            m[0][0]*v.e[0] + m[0][1]*v.e[1] + m[0][2]*v.e[2],
+            m[1][0]*v.e[0] + m[1][1]*v.e[1] + m[1][2]*v.e[2],
+            m[2][0]*v.e[0] + m[2][1]*v.e[1] + m[2][2]*v.e[2],
+        )
+    }
+}
+
+impl Default for Mat4{
+    fn default() -> Self{
+        Self::identity()
+    }
+}
+
+impl Mul for Mat4 {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        let mut r = Mat4 { m : [[0.0; 4]; 4] };
+        for i in 0..4 {
+            for j in 0..4 {
+                for k in 0..4 {
+                    r.m[i][j] += self.m[i][k] * rhs.m[k][j];
+                }
+            }
+        }
+        r
+    }
+}
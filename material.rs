@@ -1,32 +1,66 @@
 use crate::hit::*;
 use crate::ray::*;
 use crate::color::*;
-use crate::utility::random_double;
+use crate::sampler::sample_1d;
 use crate::rvec3::*;
 
-use std::rc::Rc;
+use std::sync::Arc;
 use crate::texture::*;
 use crate::perlin::*;
+use crate::pdf::*;
+use std::f64::consts::PI;
 
-pub trait Material{
-    fn scatter(&mut self,r_in : &mut Ray, rec : &HitRecord, attenuation : &mut Color, scattered : &mut Ray) -> bool;
-    fn emitted(&mut self, _u : f64, _v : f64, _p : &Point3) -> Color {
+// What a material does with an incoming ray: either a density to sample the outgoing
+// direction from (diffuse), or a single ray to follow as is (specular, skip_pdf).
+pub struct ScatterRecord{
+    pub attenuation : Color,
+    pub pdf : Option<Box<dyn Pdf>>,
+    pub skip_pdf : bool,
+    pub skip_pdf_ray : Ray,
+}
+
+impl Default for ScatterRecord{
+    fn default() -> Self{
+        Self::new()
+    }
+}
+
+impl ScatterRecord{
+    pub fn new() -> Self{
+        Self{
+            attenuation : Color::new(),
+            pdf : None,
+            skip_pdf : false,
+            skip_pdf_ray : Ray::new(),
+        }
+    }
+}
+
+pub trait Material : Send + Sync {
+    fn scatter(&self, _r_in : &mut Ray, _rec : &HitRecord, _srec : &mut ScatterRecord) -> bool {
+        false
+    }
+    fn emitted(&self, _rec : &HitRecord, _u : f64, _v : f64, _p : &Point3) -> Color {
         Color::new()
     }
+    // Density of the material scattering r_in into `scattered`, for the pdf-sampled materials.
+    fn scattering_pdf(&self, _r_in : &mut Ray, _rec : &HitRecord, _scattered : &mut Ray) -> f64 {
+        0.0
+    }
 }
 
 
 pub struct Lambertian{
-    albedo : Rc<dyn Texture>
+    albedo : Arc<dyn Texture>
 }
 
 impl Lambertian{
     pub fn new(a : Color) -> Self{
         Self{
-            albedo : Rc::new(SolidColor::new(a))
+            albedo : Arc::new(SolidColor::new(a))
         }
     }
-    pub fn new_ptr(a : Rc<dyn Texture>) -> Self {
+    pub fn new_ptr(a : Arc<dyn Texture>) -> Self {
         Self{
             albedo : a
         }
@@ -34,17 +68,17 @@ impl Lambertian{
 }
 
 impl Material for Lambertian{
-    fn scatter(&mut self,_r_in : &mut Ray, rec : &HitRecord, attenuation : &mut Color, scattered : &mut Ray) -> bool{
-        let mut scatter_direction = rec.normal + Rvec3::random_unit_vector();
-        // Catch degenerate scatter direction
-        if scatter_direction.near_zero() {
-            scatter_direction = rec.normal;
-        }
-
-        *scattered = Ray::new_time(rec.p,scatter_direction,_r_in.time());
-        *attenuation = self.albedo.value(rec.u,rec.v,rec.p);
+    fn scatter(&self,_r_in : &mut Ray, rec : &HitRecord, srec : &mut ScatterRecord) -> bool{
+        srec.attenuation = self.albedo.value(rec.u,rec.v,rec.p);
+        srec.pdf = Some(Box::new(CosinePdf::new(&rec.normal)));
+        srec.skip_pdf = false;
         true
-    }    
+    }
+
+    fn scattering_pdf(&self, _r_in : &mut Ray, rec : &HitRecord, scattered : &mut Ray) -> f64 {
+        let cos_theta = Rvec3::dot(&rec.normal, &scattered.direction());
+        if cos_theta < 0.0 { 0.0 } else { cos_theta / PI }
+    }
 }
 
 
@@ -66,11 +100,30 @@ impl Metal{
 }
 
 impl Material for Metal{
-  fn scatter(&mut self,r_in : &mut Ray, rec : &HitRecord, attenuation : &mut Color, scattered : &mut Ray) -> bool{
+  fn scatter(&self,r_in : &mut Ray, rec : &HitRecord, srec : &mut ScatterRecord) -> bool{
+        let reflected = Rvec3::reflect(Rvec3::unit_vector(&mut r_in.direction()),rec.normal);
+        srec.attenuation = self.albedo;
+
+        // A perfect mirror has no density to sample lights against.
+        if self.fuzz == 0.0 {
+            srec.pdf = None;
+            srec.skip_pdf = true;
+            srec.skip_pdf_ray = Ray::new_time(rec.p, reflected, r_in.time());
+            return true;
+        }
+
+        srec.pdf = Some(Box::new(FuzzyReflectionPdf::new(&reflected, self.fuzz)));
+        srec.skip_pdf = false;
+        true
+    }
+
+    fn scattering_pdf(&self, r_in : &mut Ray, rec : &HitRecord, scattered : &mut Ray) -> f64 {
+        // Fuzzed directions that end up below the surface are absorbed.
+        if Rvec3::dot(&scattered.direction(), &rec.normal) <= 0.0 {
+            return 0.0;
+        }
         let reflected = Rvec3::reflect(Rvec3::unit_vector(&mut r_in.direction()),rec.normal);
-        *scattered = Ray::new_time(rec.p,reflected + self.fuzz * Rvec3::random_unit_vector(),r_in.time());
-        *attenuation = self.albedo;
-        Rvec3::dot(&scattered.direction(), &rec.normal) > 0.0
+        FuzzyReflectionPdf::new(&reflected, self.fuzz).value(&scattered.direction())
     }
 }
 
@@ -95,8 +148,10 @@ impl Dielectric{
 }
 
 impl Material for Dielectric{
-    fn scatter(&mut self,r_in : &mut Ray, rec : &HitRecord, attenuation : &mut Color, scattered : &mut Ray) -> bool{
-        *attenuation = Color::new_arg(1.0,1.0,1.0);
+    fn scatter(&self,r_in : &mut Ray, rec : &HitRecord, srec : &mut ScatterRecord) -> bool{
+        srec.attenuation = Color::new_arg(1.0,1.0,1.0);
+        srec.pdf = None;
+        srec.skip_pdf = true;
         let mut refraction_ratio = self.ir;
         if rec.front_face { refraction_ratio = 1.0/self.ir;}
 
@@ -105,13 +160,13 @@ impl Material for Dielectric{
         let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
  
         let cannot_refract = refraction_ratio * sin_theta > 1.0;
-        let direction : Rvec3 = if cannot_refract || Dielectric::reflectance(cos_theta, refraction_ratio) > random_double() {
+        let direction : Rvec3 = if cannot_refract || Dielectric::reflectance(cos_theta, refraction_ratio) > sample_1d() {
             Rvec3::reflect(unit_direction,rec.normal)
         }else{ 
             Rvec3::refract(&unit_direction, &rec.normal, refraction_ratio)
         };
 
-        *scattered = Ray::new_time(rec.p, direction,r_in.time());
+        srec.skip_pdf_ray = Ray::new_time(rec.p, direction,r_in.time());
         true
     }
 }
@@ -145,11 +200,11 @@ impl Texture for NoiseTexture{
 
 
 pub struct DiffuseLight {
-    emit : Rc<dyn Texture>,
+    emit : Arc<dyn Texture>,
 }
 
 impl DiffuseLight{
-    pub fn new(a : Rc<dyn Texture>) -> Self{
+    pub fn new(a : Arc<dyn Texture>) -> Self{
         Self {
             emit : a,
         }
@@ -157,38 +212,44 @@ impl DiffuseLight{
 
     pub fn new_col(c : Color) -> Self{
         Self {
-            emit : Rc::new(SolidColor::new(c)),
+            emit : Arc::new(SolidColor::new(c)),
         }
     }
 }
 
 impl Material for DiffuseLight{
-    fn scatter(&mut self,_r_in : &mut Ray, _rec : &HitRecord, _attenuation : &mut Color, _scattered : &mut Ray) -> bool {
-        false
-    }
-    fn emitted(&mut self, u : f64, v : f64, p : &Point3) -> Color {
+    fn emitted(&self, rec : &HitRecord, u : f64, v : f64, p : &Point3) -> Color {
+        // Lights only emit from their front face, the side the surface normal points to.
+        if !rec.front_face {
+            return Color::new();
+        }
         self.emit.value(u,v,*p)
     }
 }
 
 
 pub struct Isotropic{
-    albedo : Rc<dyn Texture>,
+    albedo : Arc<dyn Texture>,
 }
 
 impl Isotropic{
     pub fn new(c : Color) -> Self{
-        Self { albedo: Rc::new(SolidColor::new(c)) }
+        Self { albedo: Arc::new(SolidColor::new(c)) }
     }
-    pub fn new_tex(a : Rc<dyn Texture>) -> Self{
+    pub fn new_tex(a : Arc<dyn Texture>) -> Self{
         Self { albedo:  a }
     }
 }
 
 impl Material for Isotropic{
-    fn scatter(&mut self,r_in : &mut Ray, rec : &HitRecord, attenuation : &mut Color, scattered : &mut Ray) -> bool {
-        *scattered = Ray::new_time(rec.p, Rvec3::random_unit_vector(), r_in.time());
-        *attenuation = self.albedo.value(rec.u,rec.v, rec.p); 
-        true   
+    fn scatter(&self,_r_in : &mut Ray, rec : &HitRecord, srec : &mut ScatterRecord) -> bool {
+        srec.attenuation = self.albedo.value(rec.u,rec.v, rec.p);
+        srec.pdf = Some(Box::new(SpherePdf));
+        srec.skip_pdf = false;
+        true
+    }
+
+    fn scattering_pdf(&self, _r_in : &mut Ray, _rec : &HitRecord, _scattered : &mut Ray) -> f64 {
+        1.0 / (4.0 * PI)
     }
 }
\ No newline at end of file
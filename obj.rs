@@ -0,0 +1,327 @@
+// Wavefront OBJ/MTL loading.
+//
+// Supports positions, texture coordinates, normals, polygonal faces (fan-triangulated), groups
+// and objects, and materials from `mtllib` files. MTL materials are mapped onto the renderer's
+// materials: emissive ones (Ke) become DiffuseLight, transparent ones (d < 1, Tr > 0 or illum
+// 4/6/7/9) Dielectric, reflective ones (illum 3, or Ks without Kd) Metal with a fuzz derived from
+// Ns, and everything else Lambertian, textured by map_Kd when present.
+
+use crate::hit::*;
+use crate::hitlist::*;
+use crate::triangle::*;
+use crate::material::*;
+use crate::texture::*;
+use crate::rvec3::*;
+use crate::color::*;
+use crate::bvh::*;
+use crate::aabb::AABB;
+use crate::interval::Interval;
+use crate::ray::Ray;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+// A problem in an OBJ or MTL file. `line` is 0 when the error isn't tied to a line.
+#[derive(Debug)]
+pub struct ObjError{
+    pub file : PathBuf,
+    pub line : usize,
+    pub message : String,
+}
+
+impl fmt::Display for ObjError{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}: {}", self.file.display(), self.message)
+        } else {
+            write!(f, "{}:{}: {}", self.file.display(), self.line, self.message)
+        }
+    }
+}
+
+impl std::error::Error for ObjError {}
+
+// A triangle mesh loaded from an OBJ file, kept in its own BVH.
+pub struct Mesh{
+    bvh : LinearBvh,
+    pub triangles : usize,
+    pub groups : Vec<String>, // Names of the groups and objects in the file, in order of appearance
+}
+
+impl Mesh{
+    pub fn load(path : &Path, default_material : Arc<dyn Material>) -> Result<Mesh, ObjError>{
+        let text = std::fs::read_to_string(path).map_err(|err| ObjError{ file : path.to_path_buf(), line : 0, message : err.to_string() })?;
+        let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+
+        let mut parser = ObjParser{
+            file : path.to_path_buf(),
+            line : 0,
+            positions : Vec::new(),
+            texcoords : Vec::new(),
+            normals : Vec::new(),
+        };
+        let mut materials : HashMap<String, Arc<dyn Material>> = HashMap::new();
+        let mut current = default_material.clone();
+        let mut groups = Vec::new();
+        let mut list = HittableList::new();
+
+        for (n, raw) in text.lines().enumerate() {
+            parser.line = n + 1;
+            let line = raw.split('#').next().unwrap_or("").trim();
+            let mut words = line.split_whitespace();
+            let keyword = match words.next() {
+                Some(keyword) => keyword,
+                None => continue,
+            };
+            let args : Vec<&str> = words.collect();
+
+            match keyword {
+                "v" => {
+                    let p = parser.floats(&args, 3)?;
+                    parser.positions.push(Point3::new_arg(p[0], p[1], p[2]));
+                }
+                "vt" => {
+                    let t = parser.floats(&args, 1)?;
+                    parser.texcoords.push([t[0], t.get(1).copied().unwrap_or(0.0)]);
+                }
+                "vn" => {
+                    let n = parser.floats(&args, 3)?;
+                    parser.normals.push(Rvec3::new_arg(n[0], n[1], n[2]));
+                }
+                "f" => {
+                    if args.len() < 3 {
+                        return Err(parser.error("a face needs at least three vertices".to_string()));
+                    }
+                    let corners = args.iter().map(|arg| parser.corner(arg)).collect::<Result<Vec<_>, _>>()?;
+                    // Fan-triangulate around the first corner; fine for the convex polygons OBJ exporters write.
+                    for k in 1..corners.len() - 1 {
+                        list.add(Arc::new(parser.triangle([corners[0], corners[k], corners[k + 1]], current.clone())));
+                    }
+                }
+                "g" | "o" => {
+                    groups.push(args.join(" "));
+                }
+                "mtllib" => {
+                    for file in args.iter() {
+                        materials.extend(load_mtl(&dir.join(file))?);
+                    }
+                }
+                "usemtl" => {
+                    let name = args.join(" ");
+                    current = match materials.get(&name) {
+                        Some(mat) => mat.clone(),
+                        None => return Err(parser.error(format!("unknown material '{}'", name))),
+                    };
+                }
+                // Smoothing groups, lines, points and free-form geometry don't affect triangle meshes.
+                _ => {}
+            }
+        }
+
+        if list.objects.is_empty() {
+            return Err(ObjError{ file : path.to_path_buf(), line : 0, message : "no faces found".to_string() });
+        }
+
+        let triangles = list.objects.len();
+        let (bvh, _) = LinearBvh::new_list_method(list, SplitMethod::Sah);
+        Ok(Mesh { bvh, triangles, groups })
+    }
+}
+
+impl Hittable for Mesh{
+    fn hit(&self, ray: &mut Ray, ray_t : &mut Interval, rec: &mut HitRecord) -> bool {
+        self.bvh.hit(ray, ray_t, rec)
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.bvh.bounding_box()
+    }
+}
+
+// Indices of one face corner into the position, texcoord and normal arrays.
+#[derive(Clone, Copy)]
+struct Corner{
+    v : usize,
+    vt : Option<usize>,
+    vn : Option<usize>,
+}
+
+struct ObjParser{
+    file : PathBuf,
+    line : usize,
+    positions : Vec<Point3>,
+    texcoords : Vec<[f64; 2]>,
+    normals : Vec<Rvec3>,
+}
+
+impl ObjParser{
+    fn error(&self, message : String) -> ObjError{
+        ObjError{ file : self.file.clone(), line : self.line, message }
+    }
+
+    fn floats(&self, args : &[&str], min : usize) -> Result<Vec<f64>, ObjError>{
+        if args.len() < min {
+            return Err(self.error(format!("expected at least {} numbers", min)));
+        }
+        args.iter().map(|arg| arg.parse::<f64>().map_err(|_| self.error(format!("invalid number '{}'", arg)))).collect()
+    }
+
+    fn index(&self, text : &str, count : usize, what : &str) -> Result<usize, ObjError>{
+        // OBJ indices start at 1; negative ones count back from the latest element.
+        let i : i64 = text.parse().map_err(|_| self.error(format!("invalid {} index '{}'", what, text)))?;
+        let resolved = if i > 0 { i - 1 } else { count as i64 + i };
+        if i == 0 || resolved < 0 || resolved >= count as i64 {
+            return Err(self.error(format!("{} index {} out of range (have {})", what, i, count)));
+        }
+        Ok(resolved as usize)
+    }
+
+    fn corner(&self, text : &str) -> Result<Corner, ObjError>{
+        // v, v/vt, v//vn or v/vt/vn
+        let mut parts = text.split('/');
+        let v = self.index(parts.next().unwrap_or(""), self.positions.len(), "vertex")?;
+        let vt = match parts.next() {
+            Some(s) if !s.is_empty() => Some(self.index(s, self.texcoords.len(), "texture coordinate")?),
+            _ => None,
+        };
+        let vn = match parts.next() {
+            Some(s) if !s.is_empty() => Some(self.index(s, self.normals.len(), "normal")?),
+            _ => None,
+        };
+        Ok(Corner{ v, vt, vn })
+    }
+
+    fn triangle(&self, c : [Corner; 3], mat : Arc<dyn Material>) -> Triangle{
+        let p = c.map(|corner| self.positions[corner.v]);
+        let normals = if c.iter().all(|corner| corner.vn.is_some()) {
+            Some(c.map(|corner| self.normals[corner.vn.unwrap()]))
+        } else {
+            None
+        };
+        let uvs = if c.iter().all(|corner| corner.vt.is_some()) {
+            Some(c.map(|corner| self.texcoords[corner.vt.unwrap()]))
+        } else {
+            None
+        };
+        Triangle::new_shaded(p, normals, uvs, mat)
+    }
+}
+
+// The MTL statements the loader understands for one material.
+struct MtlDesc{
+    kd : Color,
+    ks : Color,
+    ke : Color,
+    ns : f64,
+    ni : f64,
+    dissolve : f64,
+    illum : i32,
+    map_kd : Option<PathBuf>,
+}
+
+impl MtlDesc{
+    fn new() -> Self{
+        Self{
+            kd : Color::new_arg(0.8, 0.8, 0.8),
+            ks : Color::new(),
+            ke : Color::new(),
+            ns : 0.0,
+            ni : 1.5,
+            dissolve : 1.0,
+            illum : 2,
+            map_kd : None,
+        }
+    }
+
+    fn build(&self, file : &Path, line : usize) -> Result<Arc<dyn Material>, ObjError>{
+        let is_black = |c : &Color| c.e.iter().all(|x| *x <= 0.0);
+
+        if !is_black(&self.ke) {
+            return Ok(Arc::new(DiffuseLight::new_col(self.ke)));
+        }
+        if self.dissolve < 1.0 || matches!(self.illum, 4 | 6 | 7 | 9) {
+            return Ok(Arc::new(Dielectric::new(self.ni)));
+        }
+        if self.illum == 3 || (is_black(&self.kd) && self.map_kd.is_none() && !is_black(&self.ks)) {
+            // Map the Phong exponent to a roughness, the same way Beckmann's alpha relates to it.
+            let fuzz = (2.0 / (self.ns + 2.0)).sqrt();
+            return Ok(Arc::new(Metal::new(self.ks, fuzz)));
+        }
+        match &self.map_kd {
+            Some(map) => {
+                let tex = ImageTexture::load(&map.to_string_lossy())
+                    .map_err(|err| ObjError{ file : file.to_path_buf(), line, message : format!("{}: {}", map.display(), err) })?;
+                Ok(Arc::new(Lambertian::new_ptr(Arc::new(tex))))
+            }
+            None => Ok(Arc::new(Lambertian::new(self.kd))),
+        }
+    }
+}
+
+pub fn load_mtl(path : &Path) -> Result<HashMap<String, Arc<dyn Material>>, ObjError>{
+    let text = std::fs::read_to_string(path).map_err(|err| ObjError{ file : path.to_path_buf(), line : 0, message : err.to_string() })?;
+    let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+    let err = |line : usize, message : String| ObjError{ file : path.to_path_buf(), line, message };
+
+    let mut materials = HashMap::new();
+    // The material being read, with its name and the line it started on.
+    let mut current : Option<(String, usize, MtlDesc)> = None;
+
+    for (n, raw) in text.lines().enumerate() {
+        let line_no = n + 1;
+        let line = raw.split('#').next().unwrap_or("").trim();
+        let mut words = line.split_whitespace();
+        let keyword = match words.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        let args : Vec<&str> = words.collect();
+
+        if keyword == "newmtl" {
+            if let Some((name, start, desc)) = current.take() {
+                materials.insert(name, desc.build(path, start)?);
+            }
+            current = Some((args.join(" "), line_no, MtlDesc::new()));
+            continue;
+        }
+
+        let desc = match current.as_mut() {
+            Some((_, _, desc)) => desc,
+            None => return Err(err(line_no, format!("'{}' before any newmtl", keyword))),
+        };
+        let number = |i : usize| -> Result<f64, ObjError> {
+            let arg = args.get(i).ok_or_else(|| err(line_no, format!("'{}' needs more values", keyword)))?;
+            arg.parse().map_err(|_| err(line_no, format!("invalid number '{}'", arg)))
+        };
+        let color = || -> Result<Color, ObjError> {
+            let r = number(0)?;
+            // A single value means grey.
+            if args.len() == 1 { Ok(Color::new_arg(r, r, r)) } else { Ok(Color::new_arg(r, number(1)?, number(2)?)) }
+        };
+
+        match keyword {
+            "Kd" => desc.kd = color()?,
+            "Ks" => desc.ks = color()?,
+            "Ke" => desc.ke = color()?,
+            "Ns" => desc.ns = number(0)?,
+            "Ni" => desc.ni = number(0)?,
+            "d" => desc.dissolve = number(0)?,
+            "Tr" => desc.dissolve = 1.0 - number(0)?,
+            "illum" => desc.illum = number(0)? as i32,
+            // Options such as -bm come before the file name, which is always last.
+            "map_Kd" => match args.last() {
+                Some(file) => desc.map_kd = Some(dir.join(file)),
+                None => return Err(err(line_no, "map_Kd needs a file name".to_string())),
+            },
+            _ => {}
+        }
+    }
+
+    if let Some((name, start, desc)) = current.take() {
+        materials.insert(name, desc.build(path, start)?);
+    }
+
+    Ok(materials)
+}
@@ -0,0 +1,27 @@
+use crate::rvec3::*;
+
+// Orthonormal basis with w along a given direction.
+pub struct Onb{
+    axis : [Rvec3; 3],
+}
+
+impl Onb{
+    pub fn new(n : &Rvec3) -> Self{
+        let mut w = *n;
+        let w = Rvec3::unit_vector(&mut w);
+        // Pick a helper axis that isn't close to parallel to w.
+        let a = if w.e[0].abs() > 0.9 { Rvec3::new_arg(0.0, 1.0, 0.0) } else { Rvec3::new_arg(1.0, 0.0, 0.0) };
+        let v = Rvec3::unit_vector(&mut Rvec3::cross(&w, &a));
+        let u = Rvec3::cross(&w, &v);
+        Self { axis : [u, v, w] }
+    }
+
+    pub fn u(&self) -> Rvec3 { self.axis[0] }
+    pub fn v(&self) -> Rvec3 { self.axis[1] }
+    pub fn w(&self) -> Rvec3 { self.axis[2] }
+
+    pub fn local(&self, a : Rvec3) -> Rvec3{
+        // Vector with coordinates `a` in this basis, in world space.
+        a.e[0] * self.axis[0] + a.e[1] * self.axis[1] + a.e[2] * self.axis[2]
+    }
+}
@@ -0,0 +1,178 @@
+use crate::film::*;
+use crate::color::*;
+use crate::tonemap::*;
+use crate::aov::*;
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use image::{Rgb, RgbImage, Rgb32FImage};
+use image::codecs::hdr::HdrEncoder;
+
+// File formats a Film can be written to.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ImageFormat{
+    Ppm,  // 8-bit plain-text P3
+    Png,  // 8-bit
+    Jpeg, // 8-bit
+    Hdr,  // 32-bit float Radiance RGBE
+    Pfm,  // 32-bit float portable float map
+    Exr,  // 32-bit float OpenEXR
+}
+
+impl ImageFormat{
+    pub fn from_path(path : &Path) -> Option<ImageFormat>{
+        // Pick the format from the file extension, ignoring case.
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "ppm" => Some(ImageFormat::Ppm),
+            "png" => Some(ImageFormat::Png),
+            "jpg" | "jpeg" => Some(ImageFormat::Jpeg),
+            "hdr" => Some(ImageFormat::Hdr),
+            "pfm" => Some(ImageFormat::Pfm),
+            "exr" => Some(ImageFormat::Exr),
+            _ => None,
+        }
+    }
+
+    pub fn is_float(&self) -> bool{
+        // Float formats store linear radiance as is: no tone mapping and no sRGB encode.
+        matches!(self, ImageFormat::Hdr | ImageFormat::Pfm | ImageFormat::Exr)
+    }
+}
+
+pub fn write_image(film : &Film, path : &Path, tone_map : &ToneMapper) -> io::Result<()>{
+    let format = ImageFormat::from_path(path).ok_or_else(|| io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("unsupported image format: {} (expected .ppm, .png, .jpg, .hdr, .pfm or .exr)", path.display()),
+    ))?;
+
+    // 8-bit formats get the tone mapped film.
+    match format {
+        ImageFormat::Ppm => write_ppm(&tone_map.apply(film), &mut BufWriter::new(File::create(path)?)),
+        ImageFormat::Png | ImageFormat::Jpeg => to_rgb8_image(&tone_map.apply(film)).save(path).map_err(io::Error::other),
+        ImageFormat::Hdr => write_hdr(film, &mut BufWriter::new(File::create(path)?)),
+        ImageFormat::Pfm => write_pfm(film, &mut BufWriter::new(File::create(path)?)),
+        ImageFormat::Exr => to_rgb32f_image(film).save(path).map_err(io::Error::other),
+    }
+}
+
+pub fn write_aov(film : &Film, aov : AovType, path : &Path, tone_map : &ToneMapper) -> io::Result<()>{
+    // Write one pass of `film` on its own. 8-bit formats get a preview of data passes, while
+    // the lighting passes are tone mapped like the image.
+    let layer = film.layer(aov).ok_or_else(|| io::Error::other(format!("the film has no {} pass", aov.name())))?;
+    let float = ImageFormat::from_path(path).is_some_and(|f| f.is_float());
+    match aov {
+        AovType::Direct | AovType::Indirect => write_image(layer, path, tone_map),
+        _ if float => write_image(layer, path, tone_map),
+        _ => write_image(&preview(aov, layer), path, &ToneMapper::new()),
+    }
+}
+
+pub fn aov_path(path : &Path, aov : AovType) -> PathBuf{
+    // img.png becomes img.albedo.png.
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{}.{}.{}", stem, aov.name(), ext.to_string_lossy()),
+        None => format!("{}.{}", stem, aov.name()),
+    };
+    path.with_file_name(name)
+}
+
+pub fn write_layered_exr(film : &Film, aovs : &[AovType], path : &Path) -> io::Result<()>{
+    // One OpenEXR file with the image as R, G and B and each of `aovs` as channels of its own
+    // named after it, e.g. albedo.R or depth.Z, which compositors read as layers.
+    use exr::prelude::{AnyChannel, AnyChannels, Encoding, FlatSamples, Image, Layer, LayerAttributes, WritableImage};
+
+    let channel = |name : String, source : &Film, c : usize| {
+        let mut values = Vec::with_capacity((source.width() * source.height()) as usize);
+        for j in 0..source.height() {
+            for i in 0..source.width() {
+                values.push(source.pixel(i, j)[c] as f32);
+            }
+        }
+        AnyChannel::new(name.as_str(), FlatSamples::F32(values))
+    };
+
+    let mut channels = Vec::new();
+    for (c, name) in ["R", "G", "B"].iter().enumerate() {
+        channels.push(channel(name.to_string(), film, c));
+    }
+    for &aov in aovs {
+        let layer = film.layer(aov).ok_or_else(|| io::Error::other(format!("the film has no {} pass", aov.name())))?;
+        for (c, name) in aov.channels().iter().enumerate() {
+            channels.push(channel(format!("{}.{}", aov.name(), name), layer, c));
+        }
+    }
+
+    let size = (film.width() as usize, film.height() as usize);
+    let layer = Layer::new(size, LayerAttributes::default(), Encoding::SMALL_LOSSLESS, AnyChannels::sort(channels.into()));
+    Image::from_layer(layer).write().to_file(path).map_err(io::Error::other)
+}
+
+pub fn write_sample_counts(film : &Film, path : &Path) -> io::Result<()>{
+    // Float formats get the raw counts; 8-bit ones are scaled so the most sampled pixel is white.
+    let float = ImageFormat::from_path(path).is_some_and(|f| f.is_float());
+    let mut max_count = 1;
+    for j in 0..film.height() {
+        for i in 0..film.width() {
+            max_count = max_count.max(film.sample_count(i, j));
+        }
+    }
+    let scale = if float { 1.0 } else { 1.0 / max_count as f64 };
+
+    let mut counts = Film::new(film.width(), film.height());
+    for j in 0..film.height() {
+        for i in 0..film.width() {
+            let n = scale * film.sample_count(i, j) as f64;
+            counts.add_sample(i, j, Color::new_arg(n, n, n));
+        }
+    }
+    write_image(&counts, path, &ToneMapper::new())
+}
+
+pub fn write_ppm(film : &Film, out : &mut impl Write) -> io::Result<()>{
+    // Plain-text P3 image of a display-referred film, sRGB encoded to 8 bits per channel.
+    writeln!(out, "P3\n{} {}\n255", film.width(), film.height())?;
+    for j in 0..film.height() {
+        for i in 0..film.width() {
+            write_color(out, film.pixel(i, j))?;
+        }
+    }
+    Ok(())
+}
+
+pub fn write_hdr(film : &Film, out : &mut impl Write) -> io::Result<()>{
+    let pixels : Vec<Rgb<f32>> = to_rgb32f_image(film).pixels().copied().collect();
+    HdrEncoder::new(out)
+        .encode(&pixels, film.width() as usize, film.height() as usize)
+        .map_err(io::Error::other)
+}
+
+pub fn write_pfm(film : &Film, out : &mut impl Write) -> io::Result<()>{
+    // A negative scale marks the data as little-endian. PFM stores scanlines bottom to top.
+    writeln!(out, "PF\n{} {}\n-1.0", film.width(), film.height())?;
+    for j in (0..film.height()).rev() {
+        for i in 0..film.width() {
+            let pixel_color = film.pixel(i, j);
+            for c in 0..3 {
+                out.write_all(&(pixel_color[c] as f32).to_le_bytes())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn to_rgb8_image(film : &Film) -> RgbImage{
+    RgbImage::from_fn(film.width() as u32, film.height() as u32, |i, j| {
+        Rgb(to_rgb8(film.pixel(i as i32, j as i32)))
+    })
+}
+
+pub fn to_rgb32f_image(film : &Film) -> Rgb32FImage{
+    Rgb32FImage::from_fn(film.width() as u32, film.height() as u32, |i, j| {
+        let pixel_color = film.pixel(i as i32, j as i32);
+        Rgb([pixel_color[0] as f32, pixel_color[1] as f32, pixel_color[2] as f32])
+    })
+}
@@ -0,0 +1,137 @@
+use crate::rvec3::*;
+use crate::onb::*;
+use crate::hit::Hittable;
+use crate::sampler::sample_1d;
+
+use std::f64::consts::PI;
+
+// A probability density over directions, with respect to solid angle.
+pub trait Pdf{
+    fn value(&self, direction : &Rvec3) -> f64;
+    fn generate(&self) -> Rvec3;
+}
+
+// Uniform over the whole sphere of directions.
+pub struct SpherePdf;
+
+impl Pdf for SpherePdf{
+    fn value(&self, _direction : &Rvec3) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+
+    fn generate(&self) -> Rvec3 {
+        Rvec3::random_unit_vector()
+    }
+}
+
+// Cosine-weighted over the hemisphere around a normal, matching a Lambertian surface.
+pub struct CosinePdf{
+    uvw : Onb,
+}
+
+impl CosinePdf{
+    pub fn new(w : &Rvec3) -> Self{
+        Self { uvw : Onb::new(w) }
+    }
+}
+
+impl Pdf for CosinePdf{
+    fn value(&self, direction : &Rvec3) -> f64 {
+        let mut d = *direction;
+        let cosine_theta = Rvec3::dot(&Rvec3::unit_vector(&mut d), &self.uvw.w());
+        (cosine_theta / PI).max(0.0)
+    }
+
+    fn generate(&self) -> Rvec3 {
+        self.uvw.local(Rvec3::random_cosine_direction())
+    }
+}
+
+// Directions from `origin` towards the objects, as sampled by their pdf_value and random.
+pub struct HittablePdf<'a>{
+    objects : &'a dyn Hittable,
+    origin : Point3,
+}
+
+impl<'a> HittablePdf<'a>{
+    pub fn new(objects : &'a dyn Hittable, origin : Point3) -> Self{
+        Self { objects, origin }
+    }
+}
+
+impl Pdf for HittablePdf<'_>{
+    fn value(&self, direction : &Rvec3) -> f64 {
+        self.objects.pdf_value(&self.origin, direction)
+    }
+
+    fn generate(&self) -> Rvec3 {
+        self.objects.random(&self.origin)
+    }
+}
+
+// Directions of a fuzzy mirror: the reflected direction plus a uniform point on a sphere of
+// radius `fuzz`, normalized. A ray from the origin crosses that sphere at t1 and t2, and the
+// area-to-solid-angle change there gives (t1^2 + t2^2) / (4 pi fuzz sqrt(disc)).
+pub struct FuzzyReflectionPdf{
+    reflected : Rvec3,
+    fuzz : f64,
+}
+
+impl FuzzyReflectionPdf{
+    pub fn new(reflected : &Rvec3, fuzz : f64) -> Self{
+        let mut r = *reflected;
+        Self { reflected : Rvec3::unit_vector(&mut r), fuzz }
+    }
+}
+
+impl Pdf for FuzzyReflectionPdf{
+    fn value(&self, direction : &Rvec3) -> f64 {
+        let mut d = *direction;
+        let cos_r = Rvec3::dot(&Rvec3::unit_vector(&mut d), &self.reflected);
+        let disc = cos_r * cos_r - (1.0 - self.fuzz * self.fuzz);
+        if cos_r <= 0.0 || disc <= 0.0 {
+            return 0.0;
+        }
+
+        let root = disc.sqrt();
+        let t1 = cos_r - root;
+        let t2 = cos_r + root;
+        (t1 * t1 + t2 * t2) / (4.0 * PI * self.fuzz * root)
+    }
+
+    fn generate(&self) -> Rvec3 {
+        self.reflected + self.fuzz * Rvec3::random_unit_vector()
+    }
+}
+
+// Weight for a sample drawn with density `pdf_f`, when `pdf_g` could also have produced it.
+pub fn power_heuristic(pdf_f : f64, pdf_g : f64) -> f64{
+    let f = pdf_f * pdf_f;
+    let g = pdf_g * pdf_g;
+    if f + g == 0.0 { 0.0 } else { f / (f + g) }
+}
+
+// Even mix of two densities.
+pub struct MixturePdf<'a>{
+    p : [&'a dyn Pdf; 2],
+}
+
+impl<'a> MixturePdf<'a>{
+    pub fn new(p0 : &'a dyn Pdf, p1 : &'a dyn Pdf) -> Self{
+        Self { p : [p0, p1] }
+    }
+}
+
+impl Pdf for MixturePdf<'_>{
+    fn value(&self, direction : &Rvec3) -> f64 {
+        0.5 * self.p[0].value(direction) + 0.5 * self.p[1].value(direction)
+    }
+
+    fn generate(&self) -> Rvec3 {
+        if sample_1d() < 0.5 {
+            self.p[0].generate()
+        } else {
+            self.p[1].generate()
+        }
+    }
+}
@@ -1,23 +1,27 @@
 use crate::material::*;
 use crate::aabb::*;
-use std::rc::Rc;
+use std::sync::Arc;
 use crate::rvec3::*;
 use crate::hit::*;
-use std::cell::RefCell;
+use crate::ray::Ray;
+use crate::interval::Interval;
+use crate::utility::INFINITY;
+use crate::sampler::sample_2d;
 
 pub struct Quad{
     q : Point3,
     u : Rvec3,
     v : Rvec3,
-    mat : Rc<RefCell<dyn Material>>,
+    mat : Arc<dyn Material>,
     bbox : AABB,
     normal : Rvec3,
     d : f64,
     w : Rvec3,
+    area : f64,
 }
 
 impl Quad{
-    pub fn new(_q : Point3, _u : Rvec3, _v : Rvec3, m : Rc<RefCell<dyn Material>>) -> Self{
+    pub fn new(_q : Point3, _u : Rvec3, _v : Rvec3, m : Arc<dyn Material>) -> Self{
         let mut n = Rvec3::cross(&_u,&_v);
         let norm = Rvec3::unit_vector(&mut n);
         let _d = Rvec3::dot(&norm, &_q);
@@ -31,6 +35,7 @@ impl Quad{
             normal : norm,
             d : _d,
             w : _w,
+            area : n.length(),
         }
     }
 
@@ -51,7 +56,7 @@ impl Hittable for Quad{
     fn bounding_box(&self) -> AABB {
         self.bbox
     }
-    fn hit(&mut self, ray: &mut crate::ray::Ray, ray_t : &mut crate::interval::Interval, rec: &mut HitRecord) -> bool {
+    fn hit(&self, ray: &mut Ray, ray_t : &mut Interval, rec: &mut HitRecord) -> bool {
         let denom = Rvec3::dot(&self.normal, &ray.direction());
 
         // No hit if the ray is parallel to the plane.
@@ -78,9 +83,33 @@ impl Hittable for Quad{
         // Ray hits the 2D shape; set the rest of the hit record and return true.
         rec.t = t;
         rec.p = intersection;
-        rec.mat = Rc::clone(&self.mat);
-        rec.set_face_normal(ray, &mut self.normal);
+        rec.mat = Arc::clone(&self.mat);
+        let mut outward_normal = self.normal;
+        rec.set_face_normal(ray, &mut outward_normal);
 
         true
-    }      
+    }
+
+    fn pdf_value(&self, origin : &Point3, direction : &Rvec3) -> f64 {
+        // Convert the uniform area density to solid angle: distance^2 / (cosine * area).
+        let mut rec = HitRecord::new();
+        let mut ray = Ray::new_arg(*origin, *direction);
+        if !self.hit(&mut ray, &mut Interval::new_arg(0.001, INFINITY), &mut rec) {
+            return 0.0;
+        }
+
+        let distance_squared = rec.t * rec.t;
+        let cosine = Rvec3::dot(&ray.direction(), &self.normal).abs();
+        if cosine == 0.0 {
+            return 0.0;
+        }
+
+        distance_squared / (cosine * self.area)
+    }
+
+    fn random(&self, origin : &Point3) -> Rvec3 {
+        let (r1, r2) = sample_2d();
+        let p = self.q + (r1 * self.u) + (r2 * self.v);
+        p - *origin
+    }
 }
\ No newline at end of file
@@ -3,6 +3,7 @@ use std::ops::*;
 // point3 is just an alias for vec3, but useful for geometric clarity in the code.
 pub type Point3 = Rvec3;
 use crate::utility::*;
+use crate::sampler::sample_2d;
 
 
 #[derive(Copy,Clone)]
@@ -74,7 +75,12 @@ impl Rvec3{
     }
 
     pub fn random_unit_vector() -> Rvec3{
-        Rvec3::unit_vector(&mut Rvec3::random_in_unit_sphere())
+        // Uniform on the sphere: z uniform in [-1, 1] and an angle around it.
+        let (r1, r2) = sample_2d();
+        let z = 1.0 - 2.0 * r1;
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * std::f64::consts::PI * r2;
+        Rvec3::new_arg(r * phi.cos(), r * phi.sin(), z)
     }
 
     pub fn random_on_hemisphere(normal : &Rvec3) -> Rvec3{
@@ -85,6 +91,18 @@ impl Rvec3{
         -on_unit_sphere
     }
 
+    pub fn random_cosine_direction() -> Rvec3{
+        // Cosine-weighted direction on the hemisphere around +z.
+        let (r1, r2) = sample_2d();
+
+        let phi = 2.0 * std::f64::consts::PI * r1;
+        let x = phi.cos() * r2.sqrt();
+        let y = phi.sin() * r2.sqrt();
+        let z = (1.0 - r2).sqrt();
+
+        Rvec3::new_arg(x, y, z)
+    }
+
     pub fn near_zero(&mut self) -> bool{
         // Return true if the vector is close to zero in all dimensions.
         let s = 0.00000001; //1e-8
@@ -104,12 +122,18 @@ impl Rvec3{
     }
 
     pub fn random_in_unit_disk() -> Rvec3{
-        loop{
-            let mut p = Rvec3::new_arg(random_range(-1.0,1.0), random_range(-1.0, 1.0),0.0);
-            if p.length_squared() < 1.0{
-                return p;
-            }
+        // Shirley and Chiu's concentric mapping keeps stratified samples evenly spread on the disk.
+        let (r1, r2) = sample_2d();
+        let (a, b) = (2.0 * r1 - 1.0, 2.0 * r2 - 1.0);
+        if a == 0.0 && b == 0.0 {
+            return Rvec3::new();
         }
+        let (r, theta) = if a.abs() > b.abs() {
+            (a, std::f64::consts::FRAC_PI_4 * (b / a))
+        } else {
+            (b, std::f64::consts::FRAC_PI_2 - std::f64::consts::FRAC_PI_4 * (a / b))
+        };
+        Rvec3::new_arg(r * theta.cos(), r * theta.sin(), 0.0)
     }
 }
 
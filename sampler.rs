@@ -0,0 +1,293 @@
+// Where the random numbers of a camera sample come from.
+//
+// A sample is a point in a many-dimensional unit cube: the camera takes the first dimensions
+// (position in the pixel, time, lens) and every bounce the next ones (light choice, light
+// position, scattering direction, ...). A Sampler maps pixel, sample index and dimension to a
+// number in [0, 1), so samples of one pixel can be spread over that cube more evenly than
+// independent random numbers. The camera installs it on the rendering thread per sample, and
+// sample_1d / sample_2d hand the dimensions out in order.
+
+use crate::utility::{random_double, splitmix64};
+
+use std::cell::RefCell;
+use std::sync::Arc;
+
+const ONE_MINUS_EPSILON : f64 = 1.0 - f64::EPSILON / 2.0;
+
+pub trait Sampler : Send + Sync {
+    // `pixel` identifies the pixel (already mixed with the render seed), `index` is the sample
+    // number within it and `dim` the dimension.
+    fn get_1d(&self, pixel : u64, index : u64, dim : u32) -> f64;
+
+    // Two dimensions that should be well distributed together, e.g. a point on a light.
+    fn get_2d(&self, pixel : u64, index : u64, dim : u32) -> (f64, f64) {
+        (self.get_1d(pixel, index, dim), self.get_1d(pixel, index, dim + 1))
+    }
+}
+
+// The samplers that can be picked by name.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SamplerType{
+    Independent,
+    Stratified,
+    Halton,
+    Sobol,
+}
+
+impl SamplerType{
+    pub fn from_name(name : &str) -> Option<SamplerType>{
+        match name {
+            "independent" => Some(SamplerType::Independent),
+            "stratified" => Some(SamplerType::Stratified),
+            "halton" => Some(SamplerType::Halton),
+            "sobol" => Some(SamplerType::Sobol),
+            _ => None,
+        }
+    }
+
+    pub fn build(&self, samples_per_pixel : i32) -> Arc<dyn Sampler>{
+        match self {
+            SamplerType::Independent => Arc::new(IndependentSampler),
+            SamplerType::Stratified => Arc::new(StratifiedSampler::new(samples_per_pixel)),
+            SamplerType::Halton => Arc::new(HaltonSampler),
+            SamplerType::Sobol => Arc::new(SobolSampler),
+        }
+    }
+}
+
+// Plain uniform random numbers from the per-sample stream, like before samplers existed.
+pub struct IndependentSampler;
+
+impl Sampler for IndependentSampler{
+    fn get_1d(&self, _pixel : u64, _index : u64, _dim : u32) -> f64 {
+        random_double()
+    }
+}
+
+// Jittered strata: over samples_per_pixel samples every dimension gets one sample per stratum,
+// and 2D pairs one per cell of a grid. Strata are shuffled independently for each dimension so
+// the dimensions don't line up with each other.
+pub struct StratifiedSampler{
+    strata : u64, // Strata of a 1D dimension
+    x_strata : u64, // Columns and rows of the 2D grid
+    y_strata : u64,
+}
+
+impl StratifiedSampler{
+    pub fn new(samples_per_pixel : i32) -> Self{
+        let n = samples_per_pixel.max(1) as u64;
+        let x_strata = ((n as f64).sqrt() as u64).max(1);
+        Self { strata : n, x_strata, y_strata : n / x_strata }
+    }
+
+    fn stratum(index : u64, count : u64, seed : u64) -> u64{
+        // Past samples_per_pixel samples, every further round is shuffled differently.
+        let round = index / count;
+        permutation_element((index % count) as u32, count as u32, mix(seed, round) as u32) as u64
+    }
+}
+
+impl Sampler for StratifiedSampler{
+    fn get_1d(&self, pixel : u64, index : u64, dim : u32) -> f64 {
+        let seed = mix(pixel, dim as u64);
+        let stratum = StratifiedSampler::stratum(index, self.strata, seed);
+        let jitter = to_unit(mix(seed, !index));
+        ((stratum as f64 + jitter) / self.strata as f64).min(ONE_MINUS_EPSILON)
+    }
+
+    fn get_2d(&self, pixel : u64, index : u64, dim : u32) -> (f64, f64) {
+        let seed = mix(pixel, dim as u64);
+        let cells = self.x_strata * self.y_strata;
+        let stratum = StratifiedSampler::stratum(index, cells, seed);
+        let jitter_x = to_unit(mix(seed, !index));
+        let jitter_y = to_unit(mix(seed ^ 1, !index));
+        let x = ((stratum % self.x_strata) as f64 + jitter_x) / self.x_strata as f64;
+        let y = ((stratum / self.x_strata) as f64 + jitter_y) / self.y_strata as f64;
+        (x.min(ONE_MINUS_EPSILON), y.min(ONE_MINUS_EPSILON))
+    }
+}
+
+// Halton sequence, one prime base per dimension. Each digit is put through a random permutation
+// chosen per pixel, dimension and digit position; without it the large bases of later dimensions
+// start out as the same straight line. Dimensions past the prime table fall back to independent
+// numbers.
+pub struct HaltonSampler;
+
+const PRIMES : [u64; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53,
+    59, 61, 67, 71, 73, 79, 83, 89, 97, 101, 103, 107, 109, 113, 127, 131,
+];
+
+fn scrambled_radical_inverse(base : u64, mut a : u64, seed : u64) -> f64{
+    // Mirror the base-`base` digits of `a` around the radix point, permuting each. The leading
+    // zeros of `a` are permuted too, so keep going until the digits drop below f64 precision.
+    let inv_base = 1.0 / base as f64;
+    let mut inv_base_n = 1.0;
+    let mut result = 0.0;
+    let mut position = 0;
+    while inv_base_n > f64::EPSILON {
+        let next = a / base;
+        let digit = a - next * base;
+        let permuted = permutation_element(digit as u32, base as u32, mix(seed, position) as u32);
+        inv_base_n *= inv_base;
+        result += permuted as f64 * inv_base_n;
+        a = next;
+        position += 1;
+    }
+    result.min(ONE_MINUS_EPSILON)
+}
+
+impl Sampler for HaltonSampler{
+    fn get_1d(&self, pixel : u64, index : u64, dim : u32) -> f64 {
+        let Some(&base) = PRIMES.get(dim as usize) else { return random_double() };
+        scrambled_radical_inverse(base, index, mix(pixel, dim as u64))
+    }
+}
+
+// The first two Sobol dimensions, a (0, 2)-sequence, used for every pair of dimensions with
+// hash-based Owen scrambling and a shuffled sample order per pair (Burley, "Practical
+// Hash-based Owen Scrambling", 2020), so no direction number tables are needed.
+pub struct SobolSampler;
+
+fn sobol_second_dimension(mut index : u32) -> u32{
+    // The first dimension is index with its bits reversed, the second this.
+    let mut v : u32 = 1 << 31;
+    let mut result = 0;
+    while index != 0 {
+        if index & 1 != 0 {
+            result ^= v;
+        }
+        index >>= 1;
+        v ^= v >> 1;
+    }
+    result
+}
+
+fn laine_karras_permutation(mut x : u32, seed : u32) -> u32{
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x
+}
+
+fn nested_uniform_scramble(x : u32, seed : u32) -> u32{
+    // Owen scrambling: flipping each bit depending on a hash of the bits above it.
+    laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+impl SobolSampler{
+    fn point(pixel : u64, index : u64, dim : u32) -> (u32, u32){
+        let seed = mix(pixel, dim as u64);
+        let index = nested_uniform_scramble(index as u32, seed as u32);
+        let x = nested_uniform_scramble(index.reverse_bits(), (seed >> 32) as u32);
+        let y = nested_uniform_scramble(sobol_second_dimension(index), mix(seed, 1) as u32);
+        (x, y)
+    }
+}
+
+impl Sampler for SobolSampler{
+    fn get_1d(&self, pixel : u64, index : u64, dim : u32) -> f64 {
+        to_unit32(SobolSampler::point(pixel, index, dim).0)
+    }
+
+    fn get_2d(&self, pixel : u64, index : u64, dim : u32) -> (f64, f64) {
+        let (x, y) = SobolSampler::point(pixel, index, dim);
+        (to_unit32(x), to_unit32(y))
+    }
+}
+
+fn mix(a : u64, b : u64) -> u64{
+    splitmix64(a ^ splitmix64(b))
+}
+
+fn to_unit(h : u64) -> f64{
+    (h >> 11) as f64 / (1u64 << 53) as f64
+}
+
+fn to_unit32(x : u32) -> f64{
+    (x as f64 / 4294967296.0).min(ONE_MINUS_EPSILON)
+}
+
+fn permutation_element(mut i : u32, l : u32, p : u32) -> u32{
+    // Element i of a pseudo-random permutation of 0..l chosen by p (Kensler, "Correlated
+    // Multi-Jittered Sampling", 2013).
+    let mut w = l - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= p;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= p >> 16;
+        i ^= (i & w) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= p >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | p >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < l {
+            break;
+        }
+    }
+    i.wrapping_add(p) % l
+}
+
+// The sample being traced on this thread and the next dimension it will hand out.
+struct CurrentSample{
+    sampler : Arc<dyn Sampler>,
+    pixel : u64,
+    index : u64,
+    dim : u32,
+}
+
+thread_local! {
+    static CURRENT : RefCell<Option<CurrentSample>> = const { RefCell::new(None) };
+}
+
+pub fn start_sample(sampler : &Arc<dyn Sampler>, pixel : u64, index : u64){
+    CURRENT.with(|c| *c.borrow_mut() = Some(CurrentSample{ sampler : sampler.clone(), pixel, index, dim : 0 }));
+}
+
+pub fn set_dimension(dim : u32){
+    // Jump to a fixed dimension, so e.g. every bounce starts at the same one whatever came before.
+    CURRENT.with(|c| if let Some(s) = c.borrow_mut().as_mut() { s.dim = dim; });
+}
+
+pub fn dimension() -> u32{
+    // The next dimension the current sample will hand out, 0 outside a camera sample.
+    CURRENT.with(|c| c.borrow().as_ref().map_or(0, |s| s.dim))
+}
+
+pub fn sample_1d() -> f64{
+    // Outside a camera sample (e.g. while building a scene) this is just random_double.
+    CURRENT.with(|c| match c.borrow_mut().as_mut() {
+        Some(s) => {
+            s.dim += 1;
+            s.sampler.get_1d(s.pixel, s.index, s.dim - 1)
+        }
+        None => random_double(),
+    })
+}
+
+pub fn sample_2d() -> (f64, f64){
+    CURRENT.with(|c| match c.borrow_mut().as_mut() {
+        Some(s) => {
+            s.dim += 2;
+            s.sampler.get_2d(s.pixel, s.index, s.dim - 2)
+        }
+        None => (random_double(), random_double()),
+    })
+}
@@ -0,0 +1,556 @@
+// Loads scenes from TOML files so they can be built without writing Rust.
+//
+// A file has an optional [camera] table, named [textures.*] and [materials.*] tables and an
+// [[objects]] array. Anywhere a texture is expected a colour ([r, g, b]), the name of a texture
+// or an inline texture table can be given; materials can likewise be named or inline. Every
+// object may carry a 4x4 `matrix`, `scale`, `rotate` ({ axis, angle } in degrees), `rotate_y`
+// and `translate`, applied in that order and combined into a single transform. Top-level
+// spheres and quads with a diffuse_light material are also sampled directly as lights. An
+// [environment] table lights the scene from an HDR image or an analytic sun and sky instead
+// of camera.background, and a [[lights]] array adds point, spot and directional lights.
+// [world] bvh (and a group's bvh) is true, false, "sah" or "median" for a faster build.
+
+use crate::hit::*;
+use crate::hitlist::*;
+use crate::sphere::*;
+use crate::quad::*;
+use crate::triangle::*;
+use crate::obj::*;
+use crate::material::*;
+use crate::texture::*;
+use crate::camera::*;
+use crate::rvec3::*;
+use crate::mat4::Mat4;
+use crate::environment::*;
+use crate::sky::SunSky;
+use crate::light::*;
+use crate::sampler::SamplerType;
+use crate::filter::FilterType;
+use crate::bvh::*;
+use crate::scenes::Scene;
+use crate::utility::seed_rng;
+
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct SceneDesc{
+    #[serde(default)]
+    pub camera : CameraDesc,
+    #[serde(default)]
+    pub world : WorldDesc,
+    #[serde(default)]
+    pub textures : HashMap<String, TextureDesc>,
+    #[serde(default)]
+    pub materials : HashMap<String, MaterialDesc>,
+    #[serde(default)]
+    pub objects : Vec<ObjectDesc>,
+    #[serde(default)]
+    pub lights : Vec<LightDesc>,
+    pub environment : Option<EnvironmentDesc>,
+}
+
+// Any field left out keeps the value from Camera::new().
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct CameraDesc{
+    pub aspect_ratio : Option<f64>,
+    pub image_width : Option<i32>,
+    pub samples_per_pixel : Option<i32>,
+    pub adaptive_error : Option<f64>,
+    pub min_samples_per_pixel : Option<i32>,
+    pub max_depth : Option<i32>,
+    pub russian_roulette_depth : Option<i32>,
+    pub vfov : Option<f64>,
+    pub lookfrom : Option<[f64; 3]>,
+    pub lookat : Option<[f64; 3]>,
+    pub vup : Option<[f64; 3]>,
+    pub projection : Option<String>, // perspective or orthographic
+    pub view_width : Option<f64>,
+    pub view_height : Option<f64>,
+    pub defocus_angle : Option<f64>,
+    pub focus_dist : Option<f64>,
+    pub background : Option<[f64; 3]>,
+    pub seed : Option<u64>,
+    pub sampler : Option<String>, // independent, stratified, halton or sobol
+    pub filter : Option<String>, // box, tent, gaussian, mitchell or lanczos
+    pub filter_radius : Option<f64>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WorldDesc{
+    #[serde(default = "default_bvh")]
+    pub bvh : BvhDesc, // Put the top-level objects in a BVH
+}
+
+impl Default for WorldDesc{
+    fn default() -> Self {
+        Self { bvh : default_bvh() }
+    }
+}
+
+fn default_bvh() -> BvhDesc { BvhDesc::Enabled(true) }
+
+// `true` for a SAH BVH, `false` for none, or the split method: "sah" or "median", which builds
+// faster but traces slower.
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum BvhDesc{
+    Enabled(bool),
+    Method(String),
+}
+
+impl Default for BvhDesc{
+    fn default() -> Self {
+        BvhDesc::Enabled(false)
+    }
+}
+
+impl BvhDesc{
+    fn method(&self) -> Result<Option<SplitMethod>, String>{
+        match self {
+            BvhDesc::Enabled(false) => Ok(None),
+            BvhDesc::Enabled(true) => Ok(Some(SplitMethod::Sah)),
+            BvhDesc::Method(name) => SplitMethod::from_name(name).map(Some).ok_or_else(|| format!("unknown BVH split method '{}'", name)),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum TextureDesc{
+    Solid{ color : [f64; 3] },
+    Checker{ scale : f64, even : TextureRef, odd : TextureRef },
+    Image{ file : String },
+    Noise{ #[serde(default = "default_scale")] scale : f64 },
+}
+
+fn default_scale() -> f64 { 1.0 }
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum TextureRef{
+    Color([f64; 3]),
+    Named(String),
+    Inline(Box<TextureDesc>),
+}
+
+// What rays leaving the scene see; replaces camera.background.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum EnvironmentDesc{
+    Constant{ color : [f64; 3] },
+    // Equirectangular HDR or EXR image; `rotation` turns it about +y, in degrees.
+    Map{ file : String, #[serde(default)] rotation : f64, #[serde(default = "one")] intensity : f64 },
+    // Preetham daylight with a sun; angles in degrees, azimuth clockwise from -z towards +x.
+    Sky{ elevation : f64, #[serde(default)] azimuth : f64, #[serde(default = "turbidity")] turbidity : f64, #[serde(default = "one")] intensity : f64 },
+}
+
+// Lights without geometry; angles in degrees.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum LightDesc{
+    Point{ position : [f64; 3], intensity : [f64; 3] },
+    Spot{
+        position : [f64; 3],
+        target : [f64; 3],
+        intensity : [f64; 3],
+        #[serde(default)] inner_angle : f64,
+        outer_angle : f64,
+        #[serde(default = "one")] falloff : f64,
+    },
+    // `direction` is the way the light travels, e.g. [0, -1, 0] shines straight down.
+    Directional{ direction : [f64; 3], irradiance : [f64; 3] },
+}
+
+fn turbidity() -> f64{
+    3.0
+}
+
+fn one() -> f64{
+    1.0
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum MaterialDesc{
+    Lambertian{ albedo : TextureRef },
+    Metal{ albedo : [f64; 3], #[serde(default)] fuzz : f64 },
+    Dielectric{ ir : f64 },
+    DiffuseLight{ emit : TextureRef },
+    Isotropic{ albedo : TextureRef },
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum MaterialRef{
+    Named(String),
+    Inline(Box<MaterialDesc>),
+}
+
+#[derive(Deserialize)]
+pub struct ObjectDesc{
+    #[serde(flatten)]
+    pub shape : ShapeDesc,
+    pub matrix : Option<[[f64; 4]; 4]>, // Row-major, object to world
+    pub scale : Option<[f64; 3]>,
+    pub rotate : Option<RotateDesc>,
+    pub rotate_y : Option<f64>,
+    pub translate : Option<[f64; 3]>,
+    // Everything else in the table, including the shape's own keys. Anything that isn't one of
+    // those is a typo, since `flatten` rules out deny_unknown_fields.
+    #[serde(flatten)]
+    pub extra : HashMap<String, toml::Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RotateDesc{
+    pub axis : [f64; 3],
+    pub angle : f64,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ShapeDesc{
+    Sphere{ center : [f64; 3], center2 : Option<[f64; 3]>, radius : f64, material : MaterialRef },
+    Quad{ q : [f64; 3], u : [f64; 3], v : [f64; 3], material : MaterialRef },
+    Box{ a : [f64; 3], b : [f64; 3], material : MaterialRef },
+    Triangle{ vertices : [[f64; 3]; 3], normals : Option<[[f64; 3]; 3]>, uvs : Option<[[f64; 2]; 3]>, material : MaterialRef },
+    // Triangle mesh from a Wavefront OBJ file; `material` is used for faces without a usemtl.
+    Mesh{ file : String, material : Option<MaterialRef> },
+    // Participating medium filling the boundary object.
+    Medium{ boundary : Box<ObjectDesc>, density : f64, albedo : TextureRef },
+    // A list of objects, optionally put in its own BVH.
+    Group{ objects : Vec<ObjectDesc>, #[serde(default)] bvh : BvhDesc },
+}
+
+impl ShapeDesc{
+    fn name(&self) -> &'static str{
+        match self {
+            ShapeDesc::Sphere{ .. } => "sphere",
+            ShapeDesc::Quad{ .. } => "quad",
+            ShapeDesc::Box{ .. } => "box",
+            ShapeDesc::Triangle{ .. } => "triangle",
+            ShapeDesc::Mesh{ .. } => "mesh",
+            ShapeDesc::Medium{ .. } => "medium",
+            ShapeDesc::Group{ .. } => "group",
+        }
+    }
+
+    // The keys this shape reads from its object table, besides `type`.
+    fn fields(&self) -> &'static [&'static str]{
+        match self {
+            ShapeDesc::Sphere{ .. } => &["center", "center2", "radius", "material"],
+            ShapeDesc::Quad{ .. } => &["q", "u", "v", "material"],
+            ShapeDesc::Box{ .. } => &["a", "b", "material"],
+            ShapeDesc::Triangle{ .. } => &["vertices", "normals", "uvs", "material"],
+            ShapeDesc::Mesh{ .. } => &["file", "material"],
+            ShapeDesc::Medium{ .. } => &["boundary", "density", "albedo"],
+            ShapeDesc::Group{ .. } => &["objects", "bvh"],
+        }
+    }
+}
+
+pub fn load_scene(path : &Path) -> Result<Scene, String>{
+    let desc = read_scene(path)?;
+    build_scene_file(&desc, path)
+}
+
+pub fn read_scene(path : &Path) -> Result<SceneDesc, String>{
+    let text = std::fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+    toml::from_str(&text).map_err(|err| format!("{}: {}", path.display(), err))
+}
+
+pub fn build_scene_file(desc : &SceneDesc, path : &Path) -> Result<Scene, String>{
+    // Relative texture paths are relative to the scene file.
+    let base_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+    build_scene(desc, &base_dir).map_err(|err| format!("{}: {}", path.display(), err))
+}
+
+pub fn build_scene(desc : &SceneDesc, base_dir : &Path) -> Result<Scene, String>{
+    let mut cam = Camera::new();
+    let c = &desc.camera;
+    if let Some(v) = c.aspect_ratio { cam.aspect_ratio = v; }
+    if let Some(v) = c.image_width { cam.image_width = v; }
+    if let Some(v) = c.samples_per_pixel { cam.samples_per_pixel = v; }
+    if let Some(v) = c.adaptive_error { cam.adaptive_error = v; }
+    if let Some(v) = c.min_samples_per_pixel { cam.min_samples_per_pixel = v; }
+    if let Some(v) = c.max_depth { cam.max_depth = v; }
+    if let Some(v) = c.russian_roulette_depth { cam.russian_roulette_depth = v; }
+    if let Some(v) = c.vfov { cam.vfov = v; }
+    if let Some(v) = c.lookfrom { cam.lookfrom = vec3(v); }
+    if let Some(v) = c.lookat { cam.lookat = vec3(v); }
+    if let Some(v) = c.vup { cam.vup = vec3(v); }
+    if let Some(name) = &c.projection {
+        cam.projection = Projection::from_name(name).ok_or_else(|| format!("unknown projection '{}'", name))?;
+    }
+    if c.view_width.is_some() && c.view_height.is_some() {
+        return Err("set either camera.view_width or camera.view_height, not both".to_string());
+    }
+    if let Some(v) = c.view_width { cam.view_width = Some(v); }
+    if let Some(v) = c.view_height { cam.view_height = Some(v); }
+    if let Some(v) = c.defocus_angle { cam.defocus_angle = v; }
+    if let Some(v) = c.focus_dist { cam.focus_dist = v; }
+    if let Some(v) = c.background { cam.environment = Arc::new(ConstantEnvironment::new(vec3(v))); }
+    if let Some(v) = c.seed { cam.seed = v; }
+    if let Some(name) = &c.sampler {
+        cam.sampler = SamplerType::from_name(name).ok_or_else(|| format!("unknown sampler '{}'", name))?;
+    }
+    if let Some(name) = &c.filter {
+        cam.filter = FilterType::from_name(name).ok_or_else(|| format!("unknown filter '{}'", name))?;
+    }
+    if let Some(v) = c.filter_radius { cam.filter_radius = Some(v); }
+
+    match &desc.environment {
+        Some(_) if c.background.is_some() => return Err("set either camera.background or [environment], not both".to_string()),
+        Some(EnvironmentDesc::Constant{ color }) => cam.environment = Arc::new(ConstantEnvironment::new(vec3(*color))),
+        Some(EnvironmentDesc::Map{ file, rotation, intensity }) => {
+            let path = base_dir.join(file);
+            let map = EnvironmentMap::load(&path.to_string_lossy(), *rotation, *intensity).map_err(|err| format!("{}: {}", path.display(), err))?;
+            cam.environment = Arc::new(map);
+        }
+        Some(EnvironmentDesc::Sky{ elevation, azimuth, turbidity, intensity }) => {
+            cam.environment = Arc::new(SunSky::new(*elevation, *azimuth, *turbidity, *intensity));
+        }
+        None => {}
+    }
+
+    // Procedural textures draw random numbers while they are built.
+    seed_rng(cam.seed);
+
+    let mut builder = Builder{
+        desc,
+        base_dir : base_dir.to_path_buf(),
+        textures : HashMap::new(),
+        materials : HashMap::new(),
+        in_progress : Vec::new(),
+    };
+
+    let mut world = HittableList::new();
+    let mut lights = HittableList::new();
+    for object in desc.objects.iter(){
+        let built = builder.object(object)?;
+        if builder.is_light(object) {
+            lights.add(built.clone());
+        }
+        world.add(built);
+    }
+
+    world.number_objects();
+    let mut bvh_stats = None;
+    if let Some(method) = desc.world.bvh.method()? {
+        if !world.objects.is_empty() {
+            let (node, stats) = LinearBvh::new_list_method(world, method);
+            world = HittableList::new_arg(vec![Arc::new(node)]);
+            bvh_stats = Some(stats);
+        }
+    }
+
+    let mut delta_lights : Vec<Arc<dyn Light>> = Vec::new();
+    for light in desc.lights.iter(){
+        delta_lights.push(match light {
+            LightDesc::Point{ position, intensity } => Arc::new(PointLight::new(vec3(*position), vec3(*intensity))),
+            LightDesc::Spot{ position, target, intensity, inner_angle, outer_angle, falloff } => {
+                if position == target {
+                    return Err("spot light target must differ from its position".to_string());
+                }
+                let direction = vec3(*target) - vec3(*position);
+                Arc::new(SpotLight::new(vec3(*position), direction, vec3(*intensity), *inner_angle, *outer_angle, *falloff))
+            }
+            LightDesc::Directional{ direction, irradiance } => {
+                if direction.iter().all(|x| *x == 0.0) {
+                    return Err("directional light direction must not be zero".to_string());
+                }
+                Arc::new(DirectionalLight::new(vec3(*direction), vec3(*irradiance)))
+            }
+        });
+    }
+
+    Ok(Scene { camera : cam, world, lights, delta_lights, bvh_stats })
+}
+
+fn vec3(v : [f64; 3]) -> Rvec3{
+    Rvec3::new_arg(v[0], v[1], v[2])
+}
+
+// Turns descriptions into scene objects, sharing each named texture and material.
+struct Builder<'a>{
+    desc : &'a SceneDesc,
+    base_dir : PathBuf,
+    textures : HashMap<String, Arc<dyn Texture>>,
+    materials : HashMap<String, Arc<dyn Material>>,
+    in_progress : Vec<String>, // Named textures being built, to catch reference cycles
+}
+
+impl Builder<'_>{
+    fn texture_ref(&mut self, r : &TextureRef) -> Result<Arc<dyn Texture>, String>{
+        match r {
+            TextureRef::Color(c) => Ok(Arc::new(SolidColor::new(vec3(*c)))),
+            TextureRef::Inline(desc) => self.texture(desc),
+            TextureRef::Named(name) => {
+                if let Some(tex) = self.textures.get(name) {
+                    return Ok(tex.clone());
+                }
+                if self.in_progress.contains(name) {
+                    return Err(format!("texture '{}' refers to itself", name));
+                }
+                let desc = self.desc.textures.get(name).ok_or_else(|| format!("unknown texture '{}'", name))?;
+                self.in_progress.push(name.clone());
+                let tex = self.texture(desc);
+                self.in_progress.pop();
+                let tex = tex?;
+                self.textures.insert(name.clone(), tex.clone());
+                Ok(tex)
+            }
+        }
+    }
+
+    fn texture(&mut self, desc : &TextureDesc) -> Result<Arc<dyn Texture>, String>{
+        Ok(match desc {
+            TextureDesc::Solid{ color } => Arc::new(SolidColor::new(vec3(*color))),
+            TextureDesc::Checker{ scale, even, odd } => {
+                let even = self.texture_ref(even)?;
+                let odd = self.texture_ref(odd)?;
+                Arc::new(CheckerTexture::new(*scale, even, odd))
+            }
+            TextureDesc::Image{ file } => {
+                let path = self.base_dir.join(file);
+                let tex = ImageTexture::load(&path.to_string_lossy()).map_err(|err| format!("{}: {}", path.display(), err))?;
+                Arc::new(tex)
+            }
+            TextureDesc::Noise{ scale } => Arc::new(NoiseTexture::new_arg(*scale)),
+        })
+    }
+
+    fn material_ref(&mut self, r : &MaterialRef) -> Result<Arc<dyn Material>, String>{
+        match r {
+            MaterialRef::Inline(desc) => self.material(desc),
+            MaterialRef::Named(name) => {
+                if let Some(mat) = self.materials.get(name) {
+                    return Ok(mat.clone());
+                }
+                let desc = self.desc.materials.get(name).ok_or_else(|| format!("unknown material '{}'", name))?;
+                let mat = self.material(desc)?;
+                self.materials.insert(name.clone(), mat.clone());
+                Ok(mat)
+            }
+        }
+    }
+
+    fn is_light(&self, desc : &ObjectDesc) -> bool{
+        // Only shapes that implement pdf_value and random can be sampled.
+        let material = match &desc.shape {
+            ShapeDesc::Sphere{ center2 : None, material, .. } | ShapeDesc::Quad{ material, .. } => material,
+            _ => return false,
+        };
+        let desc = match material {
+            MaterialRef::Inline(desc) => Some(desc.as_ref()),
+            MaterialRef::Named(name) => self.desc.materials.get(name),
+        };
+        matches!(desc, Some(MaterialDesc::DiffuseLight{ .. }))
+    }
+
+    fn material(&mut self, desc : &MaterialDesc) -> Result<Arc<dyn Material>, String>{
+        Ok(match desc {
+            MaterialDesc::Lambertian{ albedo } => Arc::new(Lambertian::new_ptr(self.texture_ref(albedo)?)),
+            MaterialDesc::Metal{ albedo, fuzz } => Arc::new(Metal::new(vec3(*albedo), *fuzz)),
+            MaterialDesc::Dielectric{ ir } => Arc::new(Dielectric::new(*ir)),
+            MaterialDesc::DiffuseLight{ emit } => Arc::new(DiffuseLight::new(self.texture_ref(emit)?)),
+            MaterialDesc::Isotropic{ albedo } => Arc::new(Isotropic::new_tex(self.texture_ref(albedo)?)),
+        })
+    }
+
+    fn object(&mut self, desc : &ObjectDesc) -> Result<Arc<dyn Hittable>, String>{
+        let mut unknown : Vec<&str> = desc.extra.keys()
+            .map(String::as_str)
+            .filter(|key| *key != "type" && !desc.shape.fields().contains(key))
+            .collect();
+        if !unknown.is_empty() {
+            unknown.sort_unstable();
+            return Err(format!("unknown field(s) in {}: {}", desc.shape.name(), unknown.join(", ")));
+        }
+
+        let mut object : Arc<dyn Hittable> = match &desc.shape {
+            ShapeDesc::Sphere{ center, center2, radius, material } => {
+                let mat = self.material_ref(material)?;
+                match center2 {
+                    Some(center2) => Arc::new(Sphere::new_movable(vec3(*center), vec3(*center2), *radius, mat)),
+                    None => Arc::new(Sphere::new(vec3(*center), *radius, mat)),
+                }
+            }
+            ShapeDesc::Quad{ q, u, v, material } => {
+                Arc::new(Quad::new(vec3(*q), vec3(*u), vec3(*v), self.material_ref(material)?))
+            }
+            ShapeDesc::Box{ a, b, material } => {
+                let mat = self.material_ref(material)?;
+                HittableList::box_new(&mut vec3(*a), &mut vec3(*b), mat)
+            }
+            ShapeDesc::Triangle{ vertices, normals, uvs, material } => {
+                let mat = self.material_ref(material)?;
+                let normals = normals.map(|n| n.map(vec3));
+                Arc::new(Triangle::new_shaded(vertices.map(vec3), normals, *uvs, mat))
+            }
+            ShapeDesc::Mesh{ file, material } => {
+                let mat = match material {
+                    Some(material) => self.material_ref(material)?,
+                    None => Arc::new(Lambertian::new(Rvec3::new_arg(0.73, 0.73, 0.73))),
+                };
+                Arc::new(Mesh::load(&self.base_dir.join(file), mat).map_err(|err| err.to_string())?)
+            }
+            ShapeDesc::Medium{ boundary, density, albedo } => {
+                let boundary = self.object(boundary)?;
+                Arc::new(ConstantMedium::new_tex(boundary, *density, self.texture_ref(albedo)?))
+            }
+            ShapeDesc::Group{ objects, bvh } => {
+                let mut list = HittableList::new();
+                for object in objects.iter(){
+                    list.add(self.object(object)?);
+                }
+                match bvh.method()? {
+                    Some(method) if !list.objects.is_empty() => Arc::new(LinearBvh::new_list_method(list, method).0),
+                    _ => Arc::new(list),
+                }
+            }
+        };
+
+        let mut matrix = Mat4::identity();
+        let mut transformed = false;
+        if let Some(m) = desc.matrix {
+            matrix = Mat4::new(m) * matrix;
+            transformed = true;
+        }
+        if let Some(factors) = desc.scale {
+            matrix = Mat4::scale(vec3(factors)) * matrix;
+            transformed = true;
+        }
+        if let Some(rotate) = &desc.rotate {
+            if rotate.axis.iter().all(|x| *x == 0.0) {
+                return Err("rotate axis must not be zero".to_string());
+            }
+            matrix = Mat4::rotate(vec3(rotate.axis), rotate.angle) * matrix;
+            transformed = true;
+        }
+        if let Some(angle) = desc.rotate_y {
+            matrix = Mat4::rotate_y(angle) * matrix;
+            transformed = true;
+        }
+        if let Some(offset) = desc.translate {
+            matrix = Mat4::translate(vec3(offset)) * matrix;
+            transformed = true;
+        }
+
+        if transformed {
+            if matrix.inverse().is_none() {
+                return Err("object transform is not invertible".to_string());
+            }
+            object = Arc::new(Transform::new(object, matrix));
+        }
+
+        Ok(object)
+    }
+}
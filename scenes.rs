@@ -0,0 +1,531 @@
+use crate::hit::ConstantMedium;
+use crate::hit::Hittable;
+
+use crate::hit::Transform;
+use crate::rvec3::*;
+use crate::color::*;
+
+use crate::hitlist::*;
+use crate::sphere::*;
+
+
+use crate::camera::*;
+use crate::material::*;
+use crate::utility::random_double;
+use crate::utility::random_range;
+use crate::utility::seed_rng;
+
+use std::sync::Arc;
+
+use crate::bvh::*;
+use crate::texture::*;
+use crate::quad::*;
+use crate::environment::*;
+use crate::sky::SunSky;
+use crate::light::Light;
+
+// A camera together with the world it looks at, ready to render.
+pub struct Scene{
+    pub camera : Camera,
+    pub world : HittableList,
+    pub lights : HittableList, // Emitters that are also in `world`, sampled directly
+    pub delta_lights : Vec<Arc<dyn Light>>, // Point, spot and directional lights, which have no geometry
+    pub bvh_stats : Option<BvhStats>, // Shape of the BVH around `world`, if it has one
+}
+
+// A built-in scene that can be picked by name.
+pub struct SceneEntry{
+    pub name : &'static str,
+    pub description : &'static str,
+    pub build : fn(u64) -> Scene,
+}
+
+pub const SCENES : &[SceneEntry] = &[
+    SceneEntry{ name : "random_spheres",     description : "Book one cover: a field of random small spheres",  build : random_spheres },
+    SceneEntry{ name : "daylight",           description : "Book one cover under an analytic sun and sky",     build : daylight },
+    SceneEntry{ name : "two_spheres",        description : "Two checkered spheres",                           build : two_spheres },
+    SceneEntry{ name : "earth",              description : "Image-textured globe",                             build : earth },
+    SceneEntry{ name : "two_perlin_spheres", description : "Two spheres with Perlin marble",                   build : two_perlin_spheres },
+    SceneEntry{ name : "quads",              description : "Five coloured quads",                              build : quads },
+    SceneEntry{ name : "simple_light",       description : "Perlin spheres lit by an area light",              build : simple_light },
+    SceneEntry{ name : "cornell_box",        description : "Cornell box with two rotated boxes",               build : cornell_box },
+    SceneEntry{ name : "cornell_smoke",      description : "Cornell box with smoke-filled boxes",              build : cornell_smoke },
+    SceneEntry{ name : "final_scene",        description : "Book two cover at preview quality",                build : |seed| final_scene(400, 250, 40, seed) },
+    SceneEntry{ name : "final_scene_hq",     description : "Book two cover at full quality (very slow)",       build : |seed| final_scene(800, 10000, 40, seed) },
+];
+
+pub fn find_scene(name : &str) -> Option<&'static SceneEntry>{
+    SCENES.iter().find(|scene| scene.name == name)
+}
+
+pub fn random_spheres(seed : u64) -> Scene {
+    seed_rng(seed);
+    let mut cam = Camera::new();
+    cam.seed = seed;
+
+    //cam.image_width = 1600;
+    //cam.samples_per_pixel = 70;
+    //cam.max_depth = 500;
+    cam.image_width  = 400;
+    cam.samples_per_pixel =10;
+    cam.max_depth = 10;
+    
+    cam.vfov = 20.0;
+    cam.aspect_ratio = 16.0 / 9.0;
+    
+    cam.vfov     = 20.0;
+    cam.defocus_angle = 0.6;
+    cam.focus_dist    = 10.0;
+
+    cam.lookfrom = Point3::new_arg(13.0,2.0,3.0);
+    cam.lookat   = Point3::new_arg(0.0,0.0,0.0);
+    cam.vup      = Point3::new_arg(0.0,1.0,0.0);
+
+    cam.environment = Arc::new(ConstantEnvironment::new(Color::new_arg(0.70, 0.80, 1.00)));
+    
+    //world
+    let mut world = HittableList::new();
+
+    //let checker = Arc::new(CheckerTexture::new_color(0.32, Color::new_arg(0.2, 0.3, 0.1),Color::new_arg(0.9, 0.9, 0.9)));
+    //world.add(Arc::new(Sphere::new(Point3::new_arg(0.0,-1000.0,0.0), 1000.0, Arc::new(Lambertian::new_ptr(checker)) )));
+    
+    let ground_material = Arc::new(Lambertian::new(Color::new_arg(0.5,0.5,0.5)));
+    world.add(Arc::new(Sphere::new(Point3::new_arg(0.0,-1000.0,0.0), 1000.0, ground_material )));
+
+    for a in -11..11{
+        for b in -11..11{
+            let choose_mat = random_double();
+            let center = Point3::new_arg((a as f64) + 0.9 * random_double(), 0.2 , (b as f64) + 0.9 * random_double());
+
+            if (center - Point3::new_arg(4.0, 0.2, 0.0)).length() > 0.9 {
+                let sphere_material : Arc<dyn Material>;
+
+                if choose_mat < 0.8 {
+                    // diffuse
+                    let albedo = Color::random_vec() * Color::random_vec();
+                    sphere_material = Arc::new(Lambertian::new(albedo));
+                    let center2 = center + Rvec3::new_arg(0.0, random_range(0.0,0.5), 0.0);
+                    world.add(Arc::new(Sphere::new_movable(center,center2,0.2,sphere_material)));
+                } else if choose_mat < 0.95 {
+                    // metal
+                    let albedo = Color::random_vec_range(0.5,1.0);
+                    let fuzz = random_range(0.0, 0.5);
+                    sphere_material = Arc::new(Metal::new(albedo,fuzz));
+                    world.add(Arc::new(Sphere::new(center,0.2,sphere_material)));
+                } else {
+                    // glass
+                    sphere_material = Arc::new(Dielectric::new(1.5));
+                    world.add(Arc::new(Sphere::new(center,0.2,sphere_material)));
+                }
+            }
+        }
+    }
+    
+    let material_1 = Arc::new(Dielectric::new(1.5));
+    world.add(Arc::new(Sphere::new(Point3::new_arg( 0.0,1.0,0.0), 1.0, material_1)));
+
+    let material_2 = Arc::new(Lambertian::new(Color::new_arg(0.4,0.2,0.1)));
+    world.add(Arc::new(Sphere::new(Point3::new_arg( -4.0,   1.0,1.0), 1.0, material_2)));
+
+    let material_3 = Arc::new(Metal::new(Color::new_arg(0.7,0.6,0.5),0.0));
+    world.add(Arc::new(Sphere::new(Point3::new_arg(4.0,  1.0, 0.0), 1.0, material_3)));
+
+    // bvh
+    world.number_objects();
+    let (node, bvh_stats) = LinearBvh::new_list_method(world, SplitMethod::Sah);
+    let vc : Vec<Arc<dyn Hittable>> = vec![Arc::new(node)];
+    world = HittableList::new_arg(vc);
+
+    Scene { camera : cam, world, lights : HittableList::new(), delta_lights : Vec::new(), bvh_stats : Some(bvh_stats) }
+}
+
+pub fn daylight(seed : u64) -> Scene {
+    let mut scene = random_spheres(seed);
+    scene.camera.environment = Arc::new(SunSky::new(35.0, 120.0, 3.0, 1.0));
+    scene
+}
+
+pub fn two_spheres(seed : u64) -> Scene {
+    seed_rng(seed);
+    let mut world = HittableList::new();
+
+    let checker = Arc::new(CheckerTexture::new_color(0.32, Color::new_arg(0.2, 0.3, 0.1),Color::new_arg(0.9, 0.9, 0.9)));
+
+    world.add(Arc::new(Sphere::new(Point3::new_arg(0.0,-10.0,0.0), 10.0, Arc::new(Lambertian::new_ptr(checker.clone())) )));
+    world.add(Arc::new(Sphere::new(Point3::new_arg(0.0, 10.0,0.0), 10.0, Arc::new(Lambertian::new_ptr(checker)) )));
+
+    let mut cam = Camera::new();
+    cam.seed = seed;
+
+    cam.aspect_ratio      = 16.0 / 9.0;
+    cam.image_width       = 400;
+    cam.samples_per_pixel = 100;
+    cam.max_depth         = 50;
+
+    cam.vfov     = 20.0;
+    cam.lookfrom = Point3::new_arg(13.0,2.0,3.0);
+    cam.lookat   = Point3::new();
+    cam.vup      = Rvec3::new_arg(0.0,1.0,0.0);
+
+    cam.environment = Arc::new(ConstantEnvironment::new(Color::new_arg(0.70, 0.80, 1.00)));
+    cam.defocus_angle = 0.0;
+
+
+    // bvh
+    world.number_objects();
+    let (node, bvh_stats) = LinearBvh::new_list_method(world, SplitMethod::Sah);
+    let vc : Vec<Arc<dyn Hittable>> = vec![Arc::new(node)];
+    world = HittableList::new_arg(vc);
+
+    Scene { camera : cam, world, lights : HittableList::new(), delta_lights : Vec::new(), bvh_stats : Some(bvh_stats) }
+}
+
+
+pub fn earth(seed : u64) -> Scene {
+    seed_rng(seed);
+    let earth_texture = Arc::new(ImageTexture::new("earthmap.jpg".to_string()));
+    let earth_surface = Arc::new(Lambertian::new_ptr(earth_texture));
+    let globe = Arc::new(Sphere::new(Point3::new(), 2.0, earth_surface));
+
+    let mut cam = Camera::new();
+    cam.seed = seed;
+    let world : Vec<Arc<dyn Hittable>> = vec![globe];
+
+    cam.aspect_ratio      = 16.0 / 9.0;
+    cam.image_width       = 1200;
+    cam.samples_per_pixel = 1000;
+    cam.max_depth         = 50;
+
+    cam.vfov     = 20.0;
+    cam.lookfrom = Point3::new_arg(0.0,0.0,12.0);
+    cam.lookat   = Point3::new();
+    cam.vup      = Rvec3::new_arg(0.0,1.0,0.0);
+    cam.environment = Arc::new(ConstantEnvironment::new(Color::new_arg(0.70, 0.80, 1.00)));
+    cam.defocus_angle = 0.0;
+
+    // bvh
+    let mut world = HittableList::new_arg(world);
+    world.number_objects();
+    let (node, bvh_stats) = LinearBvh::new_list_method(world, SplitMethod::Sah);
+    let vc : Vec<Arc<dyn Hittable>> = vec![Arc::new(node)];
+    let rend = HittableList::new_arg(vc);
+
+    Scene { camera : cam, world : rend, lights : HittableList::new(), delta_lights : Vec::new(), bvh_stats : Some(bvh_stats) }
+}
+
+pub fn two_perlin_spheres(seed : u64) -> Scene {
+    seed_rng(seed);
+    let mut world = HittableList::new();
+
+    let pertext = Arc::new(NoiseTexture::new_arg(4.0));
+    world.add(Arc::new(Sphere::new(Point3::new_arg(0.0,-1000.0,0.0),1000.0, Arc::new(Lambertian::new_ptr(pertext.clone())))));
+    world.add(Arc::new(Sphere::new(Point3::new_arg(0.0,    2.0,0.0),   2.0, Arc::new(Lambertian::new_ptr(pertext)))));
+
+    let mut cam = Camera::new();
+    cam.seed = seed;
+
+    cam.aspect_ratio      = 16.0 / 9.0;
+    cam.image_width       = 400;
+    cam.samples_per_pixel = 100;
+    cam.max_depth         = 50;
+
+    cam.vfov     = 20.0;
+    cam.lookfrom = Point3::new_arg(13.0,2.0,3.0);
+    cam.lookat   = Point3::new();
+    cam.vup      = Rvec3::new_arg(0.0,1.0,0.0);
+    cam.environment = Arc::new(ConstantEnvironment::new(Color::new_arg(0.70, 0.80, 1.00)));
+    cam.defocus_angle = 0.0;
+
+    // bvh
+    world.number_objects();
+    let (node, bvh_stats) = LinearBvh::new_list_method(world, SplitMethod::Sah);
+    let vc : Vec<Arc<dyn Hittable>> = vec![Arc::new(node)];
+    world = HittableList::new_arg(vc);
+
+    Scene { camera : cam, world, lights : HittableList::new(), delta_lights : Vec::new(), bvh_stats : Some(bvh_stats) }
+}
+
+pub fn quads(seed : u64) -> Scene {
+    seed_rng(seed);
+    let mut world = HittableList::new();
+
+    // Materials
+    let left_red     = Arc::new(Lambertian::new(Color::new_arg(1.0, 0.2, 0.2)));
+    let back_green   = Arc::new(Lambertian::new(Color::new_arg(0.2, 1.0, 0.2)));
+    let right_blue   = Arc::new(Lambertian::new(Color::new_arg(0.2, 0.2, 1.0)));
+    let upper_orange = Arc::new(Lambertian::new(Color::new_arg(1.0, 0.5, 0.0)));
+    let lower_teal   = Arc::new(Lambertian::new(Color::new_arg(0.2, 0.8, 0.8)));
+
+    // Quads
+    world.add(Arc::new(Quad::new(Point3::new_arg(-3.0,-2.0, 5.0), Rvec3::new_arg(0.0, 0.0,-4.0), Rvec3::new_arg(0.0, 4.0, 0.0), left_red)));
+    world.add(Arc::new(Quad::new(Point3::new_arg(-2.0,-2.0, 0.0), Rvec3::new_arg(4.0, 0.0, 0.0), Rvec3::new_arg(0.0, 4.0, 0.0), back_green)));
+    world.add(Arc::new(Quad::new(Point3::new_arg( 3.0,-2.0, 1.0), Rvec3::new_arg(0.0, 0.0, 4.0), Rvec3::new_arg(0.0, 4.0, 0.0), right_blue)));
+    world.add(Arc::new(Quad::new(Point3::new_arg(-2.0, 3.0, 1.0), Rvec3::new_arg(4.0, 0.0, 0.0), Rvec3::new_arg(0.0, 0.0, 4.0), upper_orange)));    
+    world.add(Arc::new(Quad::new(Point3::new_arg(-2.0,-3.0, 5.0), Rvec3::new_arg(4.0, 0.0, 0.0), Rvec3::new_arg(0.0, 0.0,-4.0), lower_teal)));
+
+
+    let mut cam = Camera::new();
+    cam.seed = seed;
+
+    cam.aspect_ratio      = 1.0;
+    cam.image_width       = 400;
+    cam.samples_per_pixel = 100;
+    cam.max_depth         = 50;
+
+    cam.vfov     = 80.0;
+    cam.lookfrom = Point3::new_arg(0.0,0.0,9.0);
+    cam.lookat   = Point3::new_arg(0.0,0.0,0.0);
+    cam.vup      = Rvec3::new_arg(0.0,1.0,0.0);
+    cam.environment = Arc::new(ConstantEnvironment::new(Color::new_arg(0.70, 0.80, 1.00)));
+    cam.defocus_angle = 0.0;
+
+    // bvh
+    world.number_objects();
+    let (node, bvh_stats) = LinearBvh::new_list_method(world, SplitMethod::Sah);
+    let vc : Vec<Arc<dyn Hittable>> = vec![Arc::new(node)];
+    world = HittableList::new_arg(vc);
+
+    Scene { camera : cam, world, lights : HittableList::new(), delta_lights : Vec::new(), bvh_stats : Some(bvh_stats) }
+}
+
+pub fn simple_light(seed : u64) -> Scene {
+    seed_rng(seed);
+    let mut world = HittableList::new();
+
+    let pertext = Arc::new(NoiseTexture::new_arg(4.0));
+    world.add(Arc::new(Sphere::new(Point3::new_arg(0.0,-1000.0, 0.0), 1000.0, Arc::new(Lambertian::new_ptr(pertext.clone())))));
+    world.add(Arc::new(Sphere::new(Point3::new_arg(0.0,    2.0, 0.0),    2.0, Arc::new(Lambertian::new_ptr(pertext)))));
+
+    let difflight = Arc::new(DiffuseLight::new_col(Color::new_arg(4.0, 4.0, 4.0)));
+    let light_quad : Arc<dyn Hittable> = Arc::new(Quad::new(Point3::new_arg(3.0, 1.0, -2.0), Rvec3::new_arg(2.0, 0.0, 0.0), Rvec3::new_arg(0.0, 2.0, 0.0), difflight.clone()));
+    let light_sphere : Arc<dyn Hittable> = Arc::new(Sphere::new(Point3::new_arg(0.0, 7.0, 0.0), 2.0, difflight));
+    world.add(light_quad.clone());
+    world.add(light_sphere.clone());
+
+    let lights = HittableList::new_arg(vec![light_quad, light_sphere]);
+
+    let mut cam = Camera::new();
+    cam.seed = seed;
+
+    cam.aspect_ratio      = 16.0 / 9.0;
+    cam.image_width       = 400;
+    cam.samples_per_pixel = 100;
+    cam.max_depth         = 50;
+    cam.environment       = Arc::new(ConstantEnvironment::new(Color::new()));
+
+    cam.vfov     = 20.0;
+    cam.lookfrom = Point3::new_arg(26.0,3.0,6.0);
+    cam.lookat   = Point3::new_arg(0.0,2.0,0.0);
+    cam.vup      = Rvec3::new_arg(0.0,1.0,0.0);
+
+    cam.defocus_angle = 0.0;
+
+    // bvh
+    world.number_objects();
+    let (node, bvh_stats) = LinearBvh::new_list_method(world, SplitMethod::Sah);
+    let vc : Vec<Arc<dyn Hittable>> = vec![Arc::new(node)];
+    world = HittableList::new_arg(vc);
+
+    Scene { camera : cam, world, lights, delta_lights : Vec::new(), bvh_stats : Some(bvh_stats) }
+}
+
+pub fn cornell_box(seed : u64) -> Scene {
+    seed_rng(seed);
+    let mut world = HittableList::new();
+
+    let red   = Arc::new(Lambertian::new(Color::new_arg(0.65, 0.05, 0.05)));
+    let white = Arc::new(Lambertian::new(Color::new_arg(0.73, 0.73, 0.73)));
+    let green = Arc::new(Lambertian::new(Color::new_arg(0.12, 0.45, 0.15)));
+    let light = Arc::new(DiffuseLight::new_col(Color::new_arg(15.0, 15.0, 15.0)));
+
+    world.add(Arc::new(Quad::new(Point3::new_arg(555.0,   0.0,   0.0), Rvec3::new_arg(   0.0, 555.0, 0.0), Rvec3::new_arg(0.0,  0.0, 555.0), green)));
+    world.add(Arc::new(Quad::new(Point3::new_arg(  0.0,   0.0,   0.0), Rvec3::new_arg(   0.0, 555.0, 0.0), Rvec3::new_arg(0.0,  0.0, 555.0), red)));
+    let light_quad : Arc<dyn Hittable> = Arc::new(Quad::new(Point3::new_arg(343.0, 554.0, 332.0), Rvec3::new_arg(-130.0,   0.0, 0.0), Rvec3::new_arg(0.0,  0.0,-105.0), light));
+    world.add(light_quad.clone());
+    let lights = HittableList::new_arg(vec![light_quad]);
+    world.add(Arc::new(Quad::new(Point3::new_arg(  0.0,   0.0,   0.0), Rvec3::new_arg( 555.0,   0.0, 0.0), Rvec3::new_arg(0.0,  0.0, 555.0), white.clone())));
+    world.add(Arc::new(Quad::new(Point3::new_arg(555.0, 555.0, 555.0), Rvec3::new_arg(-555.0,   0.0, 0.0), Rvec3::new_arg(0.0,  0.0,-555.0), white.clone())));
+    world.add(Arc::new(Quad::new(Point3::new_arg(  0.0,   0.0, 555.0), Rvec3::new_arg( 555.0,   0.0, 0.0), Rvec3::new_arg(0.0,555.0,   0.0), white.clone())));
+
+    //boxes
+    let mut box1 : Arc<dyn Hittable> = HittableList::box_new(&mut Point3::new(), &mut Point3::new_arg(165.0, 330.0, 165.0), white.clone());
+    box1 = Arc::new(Transform::rotate_y(box1,15.0));
+    box1 = Arc::new(Transform::translate(box1, Rvec3::new_arg(265.0, 0.0, 295.0)));
+    world.add(box1);
+
+    let mut box2 : Arc<dyn Hittable> = HittableList::box_new(&mut Point3::new(), &mut Point3::new_arg(165.0, 165.0, 165.0), white);
+    box2 = Arc::new(Transform::rotate_y(box2,-18.0));
+    box2 = Arc::new(Transform::translate(box2, Rvec3::new_arg(130.0, 0.0, 65.0)));
+    world.add(box2);
+
+    let mut cam = Camera::new();
+    cam.seed = seed;
+
+    cam.aspect_ratio      = 1.0;
+    cam.image_width       = 600;
+    cam.samples_per_pixel = 200;
+    cam.max_depth         = 50;
+    cam.environment       = Arc::new(ConstantEnvironment::new(Color::new()));
+
+    cam.vfov     = 40.0;
+    cam.lookfrom = Point3::new_arg(278.0, 278.0, -800.0);
+    cam.lookat   = Point3::new_arg(278.0, 278.0, 0.0);
+    cam.vup      = Rvec3::new_arg(0.0,1.0,0.0);
+
+    cam.defocus_angle = 0.0;
+
+    // bvh
+    world.number_objects();
+    let (node, bvh_stats) = LinearBvh::new_list_method(world, SplitMethod::Sah);
+    let vc : Vec<Arc<dyn Hittable>> = vec![Arc::new(node)];
+    world = HittableList::new_arg(vc);
+
+    Scene { camera : cam, world, lights, delta_lights : Vec::new(), bvh_stats : Some(bvh_stats) }
+}
+
+pub fn cornell_smoke(seed : u64) -> Scene {
+    seed_rng(seed);
+    let mut world = HittableList::new();
+
+    let red   = Arc::new(Lambertian::new(Color::new_arg(0.65, 0.05, 0.05)));
+    let white = Arc::new(Lambertian::new(Color::new_arg(0.73, 0.73, 0.73)));
+    let green = Arc::new(Lambertian::new(Color::new_arg(0.12, 0.45, 0.15)));
+    let light = Arc::new(DiffuseLight::new_col(Color::new_arg(7.0, 7.0, 7.0)));
+
+    world.add(Arc::new(Quad::new(Point3::new_arg(555.0,   0.0,   0.0), Rvec3::new_arg(   0.0, 555.0, 0.0), Rvec3::new_arg(0.0,  0.0, 555.0), green)));
+    world.add(Arc::new(Quad::new(Point3::new_arg(  0.0,   0.0,   0.0), Rvec3::new_arg(   0.0, 555.0, 0.0), Rvec3::new_arg(0.0,  0.0, 555.0), red)));
+    let light_quad : Arc<dyn Hittable> = Arc::new(Quad::new(Point3::new_arg(343.0, 554.0, 332.0), Rvec3::new_arg(-130.0,   0.0, 0.0), Rvec3::new_arg(0.0,  0.0,-105.0), light));
+    world.add(light_quad.clone());
+    let lights = HittableList::new_arg(vec![light_quad]);
+    world.add(Arc::new(Quad::new(Point3::new_arg(  0.0,   0.0,   0.0), Rvec3::new_arg( 555.0,   0.0, 0.0), Rvec3::new_arg(0.0,  0.0, 555.0), white.clone())));
+    world.add(Arc::new(Quad::new(Point3::new_arg(555.0, 555.0, 555.0), Rvec3::new_arg(-555.0,   0.0, 0.0), Rvec3::new_arg(0.0,  0.0,-555.0), white.clone())));
+    world.add(Arc::new(Quad::new(Point3::new_arg(  0.0,   0.0, 555.0), Rvec3::new_arg( 555.0,   0.0, 0.0), Rvec3::new_arg(0.0,555.0,   0.0), white.clone())));
+
+    //boxes
+    let mut box1 : Arc<dyn Hittable> = HittableList::box_new(&mut Point3::new(), &mut Point3::new_arg(165.0, 330.0, 165.0), white.clone());
+    box1 = Arc::new(Transform::rotate_y(box1,15.0));
+    box1 = Arc::new(Transform::translate(box1, Rvec3::new_arg(265.0, 0.0, 295.0)));
+
+    let mut box2 : Arc<dyn Hittable> = HittableList::box_new(&mut Point3::new(), &mut Point3::new_arg(165.0, 165.0, 165.0), white);
+    box2 = Arc::new(Transform::rotate_y(box2,-18.0));
+    box2 = Arc::new(Transform::translate(box2, Rvec3::new_arg(130.0, 0.0, 65.0)));
+
+
+    world.add(Arc::new(ConstantMedium::new_col(box1, 0.01, Color::new_arg(0.0, 0.0, 0.0))));
+    world.add(Arc::new(ConstantMedium::new_col(box2, 0.01, Color::new_arg(1.0, 1.0, 1.0))));
+
+
+    let mut cam = Camera::new();
+    cam.seed = seed;
+
+    cam.aspect_ratio      = 1.0;
+    cam.image_width       = 600;
+    cam.samples_per_pixel = 200;
+    cam.max_depth         = 50;
+    cam.environment       = Arc::new(ConstantEnvironment::new(Color::new()));
+
+    cam.vfov     = 40.0;
+    cam.lookfrom = Point3::new_arg(278.0, 278.0, -800.0);
+    cam.lookat   = Point3::new_arg(278.0, 278.0, 0.0);
+    cam.vup      = Rvec3::new_arg(0.0,1.0,0.0);
+
+    cam.defocus_angle = 0.0;
+
+    // bvh
+    world.number_objects();
+    let (node, bvh_stats) = LinearBvh::new_list_method(world, SplitMethod::Sah);
+    let vc : Vec<Arc<dyn Hittable>> = vec![Arc::new(node)];
+    world = HittableList::new_arg(vc);
+
+    Scene { camera : cam, world, lights, delta_lights : Vec::new(), bvh_stats : Some(bvh_stats) }
+}
+
+pub fn final_scene(image_width : i32, samples_per_pixel : i32, max_depth : i32, seed : u64) -> Scene {
+    seed_rng(seed);
+    let mut boxes1 = HittableList::new();
+    let ground = Arc::new(Lambertian::new(Color::new_arg(0.48, 0.83, 0.53)));
+
+    let boxes_per_side: i32 = 20;
+
+    for i in 0..boxes_per_side{
+        for j in 0..boxes_per_side{
+            let w = 100.0;
+            let x0 = -1000.0 + i as f64*w;
+            let z0 = -1000.0 + j as f64*w;
+            let y0 = 0.0;
+            let x1 = x0 + w;
+            let y1 = random_range(1.0,101.0);
+            let z1 = z0 + w; 
+
+            boxes1.add(HittableList::box_new(&mut Point3::new_arg(x0, y0, z0), &mut Point3::new_arg(x1, y1, z1), ground.clone()));
+        }
+    }
+
+
+    let mut world = HittableList::new();
+
+    world.add(Arc::new(LinearBvh::new_list(boxes1)));
+
+    let light = Arc::new(DiffuseLight::new_col(Color::new_arg(7.0,7.0,7.0)));
+    let light_quad : Arc<dyn Hittable> = Arc::new(Quad::new(Point3::new_arg(123.0, 554.0, 147.0), Rvec3::new_arg(300.0, 0.0, 0.0), Rvec3::new_arg(0.0, 0.0, 265.0), light));
+    world.add(light_quad.clone());
+    let lights = HittableList::new_arg(vec![light_quad]);
+
+
+    let center1 = Point3::new_arg(400.0, 400.0, 200.0);
+    let center2 = center1 + Rvec3::new_arg(30.0,0.0,0.0);
+
+    let sphere_material = Arc::new(Lambertian::new(Color::new_arg(0.7, 0.3, 0.1)));
+    world.add(Arc::new(Sphere::new_movable(center1,center2, 50.0, sphere_material)));
+
+    world.add(Arc::new(Sphere::new(Point3::new_arg(260.0, 150.0, 45.0), 50.0, Arc::new(Dielectric::new(1.5)))));
+    world.add(Arc::new(Sphere::new(Point3::new_arg(0.0, 150.0, 145.0), 50.0 , Arc::new(Metal::new(Color::new_arg(0.8,0.8,0.9), 1.0)))));
+
+
+    let mut boundary = Arc::new(Sphere::new(Point3::new_arg(360.0, 150.0, 145.0), 70.0, Arc::new(Dielectric::new(1.5))));
+    world.add(boundary.clone());
+    world.add(Arc::new(ConstantMedium::new_col(boundary, 0.2, Color::new_arg(0.2, 0.4, 0.9))));
+    boundary = Arc::new(Sphere::new(Point3::new(), 5000.0, Arc::new(Dielectric::new(1.5)) ));
+    world.add(Arc::new(ConstantMedium::new_col(boundary, 0.0001, Color::new_arg(1.0, 1.0, 1.0))));
+
+
+    let emat = Arc::new(Lambertian::new_ptr(Arc::new(ImageTexture::new("earthmap.jpg".to_string()))));
+    world.add(Arc::new(Sphere::new(Point3::new_arg(400.0, 200.0, 400.0), 100.0, emat)));
+    let pertext = Arc::new(NoiseTexture::new_arg(0.1));    
+    world.add(Arc::new(Sphere::new(Point3::new_arg(220.0, 280.0, 300.0), 80.0, Arc::new(Lambertian::new_ptr(pertext)) )));
+
+    
+    let mut boxes2 = HittableList::new();
+    let white = Arc::new(Lambertian::new(Color::new_arg(0.73, 0.73, 0.73)));
+    let ns = 1000;
+    for _j in 0..ns{
+        boxes2.add(Arc::new(Sphere::new(Point3::random_vec_range(0.0, 165.0), 10.0 ,white.clone() )));
+    }
+
+    world.add(Arc::new(Transform::translate(Arc::new(Transform::rotate_y( Arc::new(LinearBvh::new_list(boxes2)),15.0)), Rvec3::new_arg(-100.0,270.0,395.0))));
+
+    let mut cam = Camera::new();
+    cam.seed = seed;
+
+    cam.aspect_ratio      = 1.0;
+    cam.image_width       = image_width;
+    cam.samples_per_pixel = samples_per_pixel;
+    cam.max_depth         = max_depth;
+    cam.environment       = Arc::new(ConstantEnvironment::new(Color::new_arg(0.0,0.0,0.0)));
+
+    cam.vfov     = 40.0;
+    cam.lookfrom = Point3::new_arg(478.0, 278.0, -600.0);
+    cam.lookat   = Point3::new_arg(278.0, 278.0,    0.0);
+    cam.vup      = Rvec3::new_arg(0.0,1.0,0.0);
+
+    cam.defocus_angle = 0.0;
+
+    // bvh
+    world.number_objects();
+    let (node, bvh_stats) = LinearBvh::new_list_method(world, SplitMethod::Sah);
+    let vc : Vec<Arc<dyn Hittable>> = vec![Arc::new(node)];
+    world = HittableList::new_arg(vc);
+
+
+    Scene { camera : cam, world, lights, delta_lights : Vec::new(), bvh_stats : Some(bvh_stats) }
+}
@@ -0,0 +1,150 @@
+// Analytic daylight: the Preetham, Shirley and Smits (1999) sky with a sun disk.
+//
+// The sky comes from the paper's Perez luminance and chromaticity fits, the sun from its
+// Rayleigh and aerosol transmittance. Both are in kcd/m^2 scaled by SKY_SCALE, which puts a
+// sunlit white surface close to 1. The sun is sampled as a light through the cone it subtends.
+
+use crate::rvec3::*;
+use crate::color::*;
+use crate::onb::Onb;
+use crate::environment::Environment;
+use crate::utility::degrees_to_radians;
+use crate::sampler::sample_2d;
+
+use std::f64::consts::PI;
+
+const SKY_SCALE : f64 = 0.05;
+const SUN_RADIUS : f64 = 0.2665; // Angular radius of the sun, in degrees
+const SUN_RADIANCE : f64 = 1.6e6; // Luminance of the sun outside the atmosphere, in kcd/m^2
+const GROUND_ALBEDO : f64 = 0.3; // Below the horizon the sky fades to this fraction of the horizon
+
+pub struct SunSky{
+    sun_dir : Rvec3,
+    theta_s : f64, // Zenith angle of the sun
+    zenith : [f64; 3], // Y (luminance), x and y (chromaticity) at the zenith
+    perez : [[f64; 5]; 3], // Perez A..E coefficients for Y, x and y
+    sun_color : Color,
+    cos_sun_max : f64,
+    intensity : f64,
+}
+
+impl SunSky{
+    // `elevation` is the sun's height above the horizon and `azimuth` its bearing clockwise from
+    // -z towards +x, both in degrees. Turbidity runs from about 2 (very clear) to 10 (hazy).
+    pub fn new(elevation : f64, azimuth : f64, turbidity : f64, intensity : f64) -> Self{
+        let t = turbidity.clamp(1.7, 10.0);
+        let elevation = degrees_to_radians(elevation.clamp(0.0, 90.0));
+        let azimuth = degrees_to_radians(azimuth);
+        let theta_s = PI / 2.0 - elevation;
+
+        let sun_dir = Rvec3::new_arg(azimuth.sin() * elevation.cos(), elevation.sin(), -azimuth.cos() * elevation.cos());
+
+        let perez = [
+            [ 0.1787 * t - 1.4630, -0.3554 * t + 0.4275, -0.0227 * t + 5.3251,  0.1206 * t - 2.5771, -0.0670 * t + 0.3703],
+            [-0.0193 * t - 0.2592, -0.0665 * t + 0.0008, -0.0004 * t + 0.2125, -0.0641 * t - 0.8989, -0.0033 * t + 0.0452],
+            [-0.0167 * t - 0.2608, -0.0950 * t + 0.0092, -0.0079 * t + 0.2102, -0.0441 * t - 1.6537, -0.0109 * t + 0.0529],
+        ];
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+        let zenith_y = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let th = [theta_s * theta_s * theta_s, theta_s * theta_s, theta_s, 1.0];
+        let poly = |c : [f64; 4]| c[0] * th[0] + c[1] * th[1] + c[2] * th[2] + c[3] * th[3];
+        let zenith_x = t * t * poly([0.00166, -0.00375, 0.00209, 0.0])
+            + t * poly([-0.02903, 0.06377, -0.03202, 0.00394])
+            + poly([0.11693, -0.21196, 0.06052, 0.25886]);
+        let zenith_yc = t * t * poly([0.00275, -0.00610, 0.00317, 0.0])
+            + t * poly([-0.04214, 0.08970, -0.04153, 0.00516])
+            + poly([0.15346, -0.26756, 0.06670, 0.26688]);
+
+        Self {
+            sun_dir,
+            theta_s,
+            zenith : [zenith_y.max(0.0), zenith_x, zenith_yc],
+            perez,
+            sun_color : SunSky::sun_transmittance(theta_s, t) * SUN_RADIANCE,
+            cos_sun_max : degrees_to_radians(SUN_RADIUS).cos(),
+            intensity,
+        }
+    }
+
+    fn sun_transmittance(theta_s : f64, t : f64) -> Color{
+        // Rayleigh and aerosol (Angstrom) extinction at red, green and blue wavelengths in um.
+        let zenith_degrees = theta_s.to_degrees();
+        let air_mass = 1.0 / (theta_s.cos() + 0.15 * (93.885 - zenith_degrees).max(1e-3).powf(-1.253));
+        let beta = 0.04608 * t - 0.04586;
+        let channel = |lambda : f64| {
+            let rayleigh = (-0.008735 * lambda.powf(-4.08) * air_mass).exp();
+            let aerosol = (-beta * lambda.powf(-1.3) * air_mass).exp();
+            rayleigh * aerosol
+        };
+        Color::new_arg(channel(0.680), channel(0.550), channel(0.440))
+    }
+
+    fn perez_fn(c : &[f64; 5], cos_theta : f64, gamma : f64) -> f64{
+        (1.0 + c[0] * (c[1] / cos_theta).exp()) * (1.0 + c[2] * (c[3] * gamma).exp() + c[4] * gamma.cos() * gamma.cos())
+    }
+
+    fn sky(&self, d : &Rvec3) -> Color{
+        // The fits only hold above the horizon, so keep cos(theta) a little positive.
+        let cos_theta = d.e[1].max(0.01);
+        let gamma = Rvec3::dot(d, &self.sun_dir).clamp(-1.0, 1.0).acos();
+
+        let mut xyy = [0.0; 3];
+        for (k, c) in self.perez.iter().enumerate() {
+            xyy[k] = self.zenith[k] * SunSky::perez_fn(c, cos_theta, gamma) / SunSky::perez_fn(c, 1.0, self.theta_s);
+        }
+
+        xyy_to_rgb(xyy[0], xyy[1], xyy[2])
+    }
+}
+
+impl Environment for SunSky{
+    fn value(&self, direction : &Rvec3) -> Color {
+        let mut d = *direction;
+        let d = Rvec3::unit_vector(&mut d);
+
+        let mut color = self.sky(&d);
+        if d.e[1] < 0.0 {
+            color = GROUND_ALBEDO * color;
+        } else if Rvec3::dot(&d, &self.sun_dir) >= self.cos_sun_max {
+            color += self.sun_color;
+        }
+
+        (self.intensity * SKY_SCALE) * color
+    }
+
+    fn is_sampled(&self) -> bool {
+        true
+    }
+
+    fn pdf_value(&self, direction : &Rvec3) -> f64 {
+        // Uniform over the sun's cone; the smooth sky is left to material sampling.
+        let mut d = *direction;
+        if Rvec3::dot(&Rvec3::unit_vector(&mut d), &self.sun_dir) < self.cos_sun_max {
+            return 0.0;
+        }
+        1.0 / (2.0 * PI * (1.0 - self.cos_sun_max))
+    }
+
+    fn random(&self) -> Rvec3 {
+        let (r1, r2) = sample_2d();
+        let z = 1.0 - r1 * (1.0 - self.cos_sun_max);
+        let phi = 2.0 * PI * r2;
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        Onb::new(&self.sun_dir).local(Rvec3::new_arg(r * phi.cos(), r * phi.sin(), z))
+    }
+}
+
+fn xyy_to_rgb(luminance : f64, x : f64, y : f64) -> Color{
+    // CIE xyY to linear sRGB (D65).
+    if y <= 0.0 {
+        return Color::new();
+    }
+    let big_x = x * luminance / y;
+    let big_z = (1.0 - x - y) * luminance / y;
+    Color::new_arg(
+        ( 3.2404542 * big_x - 1.5371385 * luminance - 0.4985314 * big_z).max(0.0),
+        (-0.9692660 * big_x + 1.8760108 * luminance + 0.0415560 * big_z).max(0.0),
+        ( 0.0556434 * big_x - 0.2040259 * luminance + 1.0572252 * big_z).max(0.0),
+    )
+}
@@ -3,14 +3,17 @@ use crate::rvec3::*;
 use crate::ray::*;
 use crate::interval::*;
 use crate::material::*;
-use std::rc::Rc;
-use std::cell::RefCell;
+use std::sync::Arc;
 use crate::aabb::AABB;
+use crate::onb::Onb;
+use crate::utility::INFINITY;
+use crate::sampler::sample_2d;
+use std::f64::consts::PI;
 
 pub struct Sphere {
     center : Point3,
     radius : f64,
-    mat : Rc<RefCell<dyn Material>>,
+    mat : Arc<dyn Material>,
 
     is_moving : bool,
     center_vec : Rvec3,
@@ -19,7 +22,7 @@ pub struct Sphere {
 
 
 impl Sphere{
-    pub fn new(cnt : Point3, rad : f64, mt : Rc<RefCell<dyn Material>>) -> Self{
+    pub fn new(cnt : Point3, rad : f64, mt : Arc<dyn Material>) -> Self{
         let rvec = Rvec3::new_arg(rad, rad, rad);
         Self{
             center : cnt,
@@ -30,7 +33,7 @@ impl Sphere{
             bbox : AABB::new_points(cnt - rvec, cnt + rvec)
         }
     }
-    pub fn new_movable(cnt : Point3, cnt2 : Point3, rad : f64, mt : Rc<RefCell<dyn Material>>) -> Self{
+    pub fn new_movable(cnt : Point3, cnt2 : Point3, rad : f64, mt : Arc<dyn Material>) -> Self{
         let rvec = Rvec3::new_arg(rad, rad, rad);
         let box1 = AABB::new_points(cnt - rvec, cnt + rvec);
         let box2 = AABB::new_points(cnt2 - rvec, cnt2 + rvec);
@@ -50,6 +53,18 @@ impl Sphere{
         self.center + time*self.center_vec
     }
 
+    fn random_to_sphere(radius : f64, distance_squared : f64) -> Rvec3{
+        // Uniform direction within the cone around +z that a sphere at this distance subtends.
+        let (r1, r2) = sample_2d();
+        let z = 1.0 + r2 * ((1.0 - radius * radius / distance_squared).max(0.0).sqrt() - 1.0);
+
+        let phi = 2.0 * PI * r1;
+        let x = phi.cos() * (1.0 - z * z).sqrt();
+        let y = phi.sin() * (1.0 - z * z).sqrt();
+
+        Rvec3::new_arg(x, y, z)
+    }
+
     pub fn get_sphere_uv(mut p : Point3, u : &mut f64, v : &mut f64){
         // p: a given point on the sphere of radius one, centered at the origin.
         // u: returned value [0,1] of angle around the Y axis from X=-1.
@@ -67,7 +82,7 @@ impl Sphere{
 }
 
 impl Hittable for Sphere{
-    fn hit(&mut self, r: &mut Ray, ray_t : &mut Interval, rec: &mut HitRecord) -> bool{
+    fn hit(&self, r: &mut Ray, ray_t : &mut Interval, rec: &mut HitRecord) -> bool{
         let mut calc_center = self.center;
         if self.is_moving {
             calc_center = self.sphere_center(r.time());
@@ -93,7 +108,7 @@ impl Hittable for Sphere{
         rec.p = r.at(rec.t);
         let mut outward_normal = (rec.p - calc_center) / self.radius;
         rec.set_face_normal(r, &mut outward_normal);
-        rec.mat = Rc::clone(&self.mat);
+        rec.mat = Arc::clone(&self.mat);
         Sphere::get_sphere_uv(outward_normal, &mut rec.u, &mut rec.v);
         
         true
@@ -102,4 +117,25 @@ impl Hittable for Sphere{
     fn bounding_box(&self) -> AABB {
         self.bbox
     }
+
+    fn pdf_value(&self, origin : &Point3, direction : &Rvec3) -> f64 {
+        // Uniform over the cone of directions the sphere subtends; only works for stationary spheres.
+        let mut rec = HitRecord::new();
+        if !self.hit(&mut Ray::new_arg(*origin, *direction), &mut Interval::new_arg(0.001, INFINITY), &mut rec) {
+            return 0.0;
+        }
+
+        let dist_squared = (self.center - *origin).length_squared();
+        let cos_theta_max = (1.0 - self.radius * self.radius / dist_squared).max(0.0).sqrt();
+        let solid_angle = 2.0 * PI * (1.0 - cos_theta_max);
+
+        1.0 / solid_angle
+    }
+
+    fn random(&self, origin : &Point3) -> Rvec3 {
+        let mut direction = self.center - *origin;
+        let distance_squared = direction.length_squared();
+        let uvw = Onb::new(&direction);
+        uvw.local(Sphere::random_to_sphere(self.radius, distance_squared))
+    }
 }
\ No newline at end of file
@@ -1,10 +1,10 @@
 use crate::interval::Interval;
 use crate::rvec3::*;
 use crate::color::*;
-use std::rc::Rc;
+use std::sync::Arc;
 
 
-pub trait Texture{
+pub trait Texture : Send + Sync {
     fn value(&self, u : f64, v : f64, p : Point3) -> Color;
 }
 
@@ -34,13 +34,13 @@ impl Texture for SolidColor{
 
 pub struct CheckerTexture{
     inv_scale : f64,
-    even : Rc<dyn Texture>,
-    odd : Rc<dyn Texture>,
+    even : Arc<dyn Texture>,
+    odd : Arc<dyn Texture>,
 }
 
 
 impl CheckerTexture{
-    pub fn new(_scale : f64, _even : Rc<dyn Texture>, _odd : Rc<dyn Texture>) -> Self {
+    pub fn new(_scale : f64, _even : Arc<dyn Texture>, _odd : Arc<dyn Texture>) -> Self {
         Self { 
             inv_scale : 1.0 / _scale,
             even : _even,
@@ -51,8 +51,8 @@ impl CheckerTexture{
     pub fn new_color(_scale : f64, c1 : Color, c2 : Color) -> Self{
         Self { 
             inv_scale : 1.0 / _scale,
-            even : Rc::new(SolidColor::new(c1)),
-            odd : Rc::new(SolidColor::new(c2)),
+            even : Arc::new(SolidColor::new(c1)),
+            odd : Arc::new(SolidColor::new(c2)),
         }     
     }
 }
@@ -89,9 +89,13 @@ pub struct ImageTexture{
 
 impl ImageTexture{
     pub fn new(filename : String) -> Self{
-        Self { 
-            img : ImageReader::open(filename).unwrap().decode().unwrap()
-        }
+        ImageTexture::load(&filename).unwrap()
+    }
+
+    pub fn load(filename : &str) -> image::ImageResult<Self>{
+        Ok(Self { 
+            img : ImageReader::open(filename)?.decode()?
+        })
     }
 
     pub fn clamp(x : i32, low : i32 , high : i32) -> i32{
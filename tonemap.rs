@@ -0,0 +1,163 @@
+// Tone mapping: how the unbounded radiance of the float framebuffer is squeezed into the [0, 1]
+// range of a display before the sRGB transfer function and 8-bit quantization. Float outputs
+// skip this and keep the raw radiance.
+
+use crate::color::*;
+use crate::film::*;
+
+// The operators that can be picked by name.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ToneMapType{
+    Clamp,
+    Reinhard,
+    ReinhardExtended,
+    Aces,
+    Hable,
+    AgX,
+}
+
+impl ToneMapType{
+    pub fn from_name(name : &str) -> Option<ToneMapType>{
+        match name {
+            "clamp" => Some(ToneMapType::Clamp),
+            "reinhard" => Some(ToneMapType::Reinhard),
+            "reinhard-extended" => Some(ToneMapType::ReinhardExtended),
+            "aces" => Some(ToneMapType::Aces),
+            "hable" => Some(ToneMapType::Hable),
+            "agx" => Some(ToneMapType::AgX),
+            _ => None,
+        }
+    }
+}
+
+pub struct ToneMapper{
+    pub operator : ToneMapType,
+    pub exposure : f64, // In stops: the radiance is scaled by 2^exposure first
+    pub white : Option<f64>, // Radiance that maps to white for reinhard-extended and hable
+}
+
+impl Default for ToneMapper{
+    fn default() -> Self{
+        Self::new()
+    }
+}
+
+impl ToneMapper{
+    pub fn new() -> Self{
+        // Plain clamping, what 8-bit output always did.
+        Self { operator : ToneMapType::Clamp, exposure : 0.0, white : None }
+    }
+
+    pub fn apply(&self, film : &Film) -> Film{
+        // Display-referred copy of the film: linear values in [0, 1], ready for the sRGB encode.
+        let scale = self.exposure.exp2();
+        let white = self.white.unwrap_or_else(|| match self.operator {
+            // Without a white point the brightest pixel just reaches white.
+            ToneMapType::ReinhardExtended => (0..film.height())
+                .flat_map(|j| (0..film.width()).map(move |i| (i, j)))
+                .map(|(i, j)| luminance(scale * film.pixel(i, j)))
+                .fold(1.0, f64::max),
+            _ => HABLE_WHITE,
+        });
+
+        let mut display = Film::new(film.width(), film.height());
+        for j in 0..film.height() {
+            for i in 0..film.width() {
+                let c = scale * film.pixel(i, j);
+                let mapped = match self.operator {
+                    ToneMapType::Clamp => c,
+                    ToneMapType::Reinhard => scale_luminance(c, |l| l / (1.0 + l)),
+                    ToneMapType::ReinhardExtended => scale_luminance(c, |l| l * (1.0 + l / (white * white)) / (1.0 + l)),
+                    ToneMapType::Aces => aces(c),
+                    ToneMapType::Hable => map_channels(c, |x| hable(x) / hable(white)),
+                    ToneMapType::AgX => agx(c),
+                };
+                display.add_sample(i, j, map_channels(mapped, |x| x.clamp(0.0, 1.0)));
+            }
+        }
+        display
+    }
+}
+
+fn map_channels(c : Color, f : impl Fn(f64) -> f64) -> Color{
+    Color::new_arg(f(c[0]), f(c[1]), f(c[2]))
+}
+
+fn scale_luminance(c : Color, f : impl Fn(f64) -> f64) -> Color{
+    // Map the luminance and scale the color along with it, which keeps its hue (Reinhard et al.,
+    // "Photographic Tone Reproduction for Digital Images", 2002).
+    let l = luminance(c);
+    if l <= 0.0 {
+        return Color::new();
+    }
+    (f(l) / l) * c
+}
+
+fn mul_matrix(m : &[[f64; 3]; 3], c : Color) -> Color{
+    Color::new_arg(
+        m[0][0] * c[0] + m[0][1] * c[1] + m[0][2] * c[2],
+        m[1][0] * c[0] + m[1][1] * c[1] + m[1][2] * c[2],
+        m[2][0] * c[0] + m[2][1] * c[1] + m[2][2] * c[2],
+    )
+}
+
+// Stephen Hill's fit of the ACES reference rendering and sRGB output transforms, applied in the
+// ACES working space.
+const ACES_INPUT : [[f64; 3]; 3] = [
+    [0.59719, 0.35458, 0.04823],
+    [0.07600, 0.90834, 0.01566],
+    [0.02840, 0.13383, 0.83777],
+];
+const ACES_OUTPUT : [[f64; 3]; 3] = [
+    [1.60475, -0.53108, -0.07367],
+    [-0.10208, 1.10813, -0.00605],
+    [-0.00327, -0.07276, 1.07602],
+];
+
+fn aces(c : Color) -> Color{
+    let v = mul_matrix(&ACES_INPUT, c);
+    let v = map_channels(v, |x| {
+        let a = x * (x + 0.0245786) - 0.000090537;
+        let b = x * (0.983729 * x + 0.4329510) + 0.238081;
+        a / b
+    });
+    mul_matrix(&ACES_OUTPUT, v)
+}
+
+// John Hable's filmic curve from Uncharted 2, with its default white point.
+const HABLE_WHITE : f64 = 11.2;
+
+fn hable(x : f64) -> f64{
+    let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+    (x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f) - e / f
+}
+
+// AgX by Troy Sobotka, in Benjamin Wrensch's polynomial approximation: compress into a slightly
+// desaturated space, take a log encoding over a fixed range of stops and run it through a sigmoid.
+// Very bright colors drift towards white instead of clipping to a saturated hue.
+const AGX_INSET : [[f64; 3]; 3] = [
+    [0.842479062253094, 0.0784335999999992, 0.0792237451477643],
+    [0.0423282422610123, 0.878468636469772, 0.0791661274605434],
+    [0.0423756549057051, 0.0784336, 0.879142973793104],
+];
+const AGX_OUTSET : [[f64; 3]; 3] = [
+    [1.19687900512017, -0.0980208811401368, -0.0990297440797205],
+    [-0.0528968517574562, 1.15190312990417, -0.0989611768448433],
+    [-0.0529716355144438, -0.0980434501171241, 1.15107367264116],
+];
+const AGX_MIN_EV : f64 = -12.47393;
+const AGX_MAX_EV : f64 = 4.026069;
+
+fn agx(c : Color) -> Color{
+    let v = mul_matrix(&AGX_INSET, c);
+    let v = map_channels(v, |x| {
+        let ev = x.max(1e-10).log2().clamp(AGX_MIN_EV, AGX_MAX_EV);
+        let x = (ev - AGX_MIN_EV) / (AGX_MAX_EV - AGX_MIN_EV);
+        let (x2, x4) = (x * x, x * x * x * x);
+        15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x - 0.00232
+    });
+    // The sigmoid's output is display encoded with a 2.2 gamma; undo that so it can go through
+    // the sRGB encode like the other operators.
+    let v = mul_matrix(&AGX_OUTSET, v);
+    map_channels(v, |x| x.max(0.0).powf(2.2))
+}
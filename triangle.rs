@@ -0,0 +1,128 @@
+use crate::material::*;
+use crate::aabb::*;
+use std::sync::Arc;
+use crate::rvec3::*;
+use crate::hit::*;
+
+pub struct Triangle{
+    p : [Point3; 3],
+    normals : Option<[Rvec3; 3]>, // Per-vertex shading normals
+    uvs : Option<[[f64; 2]; 3]>,  // Per-vertex texture coordinates
+    mat : Arc<dyn Material>,
+    bbox : AABB,
+    normal : Rvec3, // Geometric normal, following the winding p0 -> p1 -> p2
+}
+
+impl Triangle{
+    pub fn new(a : Point3, b : Point3, c : Point3, m : Arc<dyn Material>) -> Self{
+        Triangle::new_shaded([a, b, c], None, None, m)
+    }
+
+    pub fn new_shaded(p : [Point3; 3], normals : Option<[Rvec3; 3]>, uvs : Option<[[f64; 2]; 3]>, m : Arc<dyn Material>) -> Self{
+        let mut n = Rvec3::cross(&(p[1] - p[0]), &(p[2] - p[0]));
+        let bbox = AABB::new_boxes(AABB::new_points(p[0], p[1]), AABB::new_points(p[2], p[2])).pad();
+        Self{
+            p,
+            normals,
+            uvs,
+            mat : m,
+            bbox,
+            normal : Rvec3::unit_vector(&mut n),
+        }
+    }
+}
+
+impl Hittable for Triangle{
+    fn bounding_box(&self) -> AABB {
+        self.bbox
+    }
+
+    fn hit(&self, ray: &mut crate::ray::Ray, ray_t : &mut crate::interval::Interval, rec: &mut HitRecord) -> bool {
+        // Watertight ray/triangle test (Woop, Benthin and Wald, 2013): shear the vertices into a
+        // space where the ray runs along +z from the origin, then do the 2D edge tests there.
+        // Rays through a shared edge or vertex hit at least one of the triangles touching it.
+        let dir = ray.direction();
+        let orig = ray.origin();
+
+        // Use the largest direction component as z so the shear is well conditioned.
+        let kz = if dir.e[0].abs() > dir.e[1].abs() {
+            if dir.e[0].abs() > dir.e[2].abs() { 0 } else { 2 }
+        } else if dir.e[1].abs() > dir.e[2].abs() { 1 } else { 2 };
+        let mut kx = (kz + 1) % 3;
+        let mut ky = (kx + 1) % 3;
+        // Swap to keep the winding, and so the sign of the edge functions, unchanged.
+        if dir.e[kz] < 0.0 {
+            std::mem::swap(&mut kx, &mut ky);
+        }
+
+        let sx = dir.e[kx] / dir.e[kz];
+        let sy = dir.e[ky] / dir.e[kz];
+        let sz = 1.0 / dir.e[kz];
+
+        let a = self.p[0] - orig;
+        let b = self.p[1] - orig;
+        let c = self.p[2] - orig;
+
+        let ax = a.e[kx] - sx * a.e[kz];
+        let ay = a.e[ky] - sy * a.e[kz];
+        let bx = b.e[kx] - sx * b.e[kz];
+        let by = b.e[ky] - sy * b.e[kz];
+        let cx = c.e[kx] - sx * c.e[kz];
+        let cy = c.e[ky] - sy * c.e[kz];
+
+        // Scaled barycentric coordinates; a point on an edge gives exactly 0 for that edge.
+        let u = cx * by - cy * bx;
+        let v = ax * cy - ay * cx;
+        let w = bx * ay - by * ax;
+
+        if (u < 0.0 || v < 0.0 || w < 0.0) && (u > 0.0 || v > 0.0 || w > 0.0) {
+            return false;
+        }
+
+        let det = u + v + w;
+        if det == 0.0 {
+            return false;
+        }
+
+        // Return false if the hit point parameter t is outside the ray interval.
+        let t_scaled = u * sz * a.e[kz] + v * sz * b.e[kz] + w * sz * c.e[kz];
+        let t = t_scaled / det;
+        if !ray_t.surrounds(t) {
+            return false;
+        }
+
+        let b0 = u / det;
+        let b1 = v / det;
+        let b2 = w / det;
+
+        rec.t = t;
+        rec.p = ray.at(t);
+        rec.mat = Arc::clone(&self.mat);
+
+        let mut outward_normal = self.normal;
+        rec.set_face_normal(ray, &mut outward_normal);
+
+        // Smooth shading: interpolate the vertex normals and turn the result to the side the
+        // ray arrived from, like the geometric normal.
+        if let Some(n) = &self.normals {
+            let mut shading = b0 * n[0] + b1 * n[1] + b2 * n[2];
+            if !shading.near_zero() {
+                shading = Rvec3::unit_vector(&mut shading);
+                rec.normal = if Rvec3::dot(&shading, &rec.normal) < 0.0 { -shading } else { shading };
+            }
+        }
+
+        match &self.uvs {
+            Some(uv) => {
+                rec.u = b0 * uv[0][0] + b1 * uv[1][0] + b2 * uv[2][0];
+                rec.v = b0 * uv[0][1] + b1 * uv[1][1] + b2 * uv[2][1];
+            }
+            None => {
+                rec.u = b1;
+                rec.v = b2;
+            }
+        }
+
+        true
+    }
+}
@@ -1,25 +1,48 @@
 //use std::f64::INFINITY;
 
 use rand::Rng;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use std::cell::RefCell;
 
 pub const INFINITY : f64 = f64::INFINITY;
 
+thread_local! {
+    // Every thread draws from its own generator; unseeded threads start from OS entropy.
+    static RNG : RefCell<StdRng> = RefCell::new(StdRng::from_entropy());
+}
+
 pub fn degrees_to_radians(degrees : f64) -> f64{
     degrees * std::f64::consts::PI / 180.0
 }
 
+pub fn seed_rng(seed : u64) {
+    // Restart the calling thread's random sequence from seed.
+    RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed));
+}
+
+pub fn seed_stream(seed : u64, pixel : u64, sample : u64) {
+    // Switch the calling thread to the stream belonging to one sample of one pixel, so the
+    // numbers a sample sees don't depend on which thread renders it or in what order.
+    seed_rng(splitmix64(seed ^ splitmix64(pixel ^ splitmix64(sample))));
+}
+
+pub fn splitmix64(x : u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
 pub fn random_double() -> f64 {
-    let mut rng = rand::thread_rng();
-    rng.gen::<f64>()
+    RNG.with(|rng| rng.borrow_mut().gen::<f64>())
 }
 
 
 pub fn random_range(min : f64, max : f64) -> f64 {
-    let mut rng = rand::thread_rng();
-    rng.gen_range(min..max)
+    RNG.with(|rng| rng.borrow_mut().gen_range(min..max))
 }
 
 pub fn random_int(a : i32, b : i32) -> i32{
-    let mut rng = rand::thread_rng();
-    rng.gen_range(a..b)
+    RNG.with(|rng| rng.borrow_mut().gen_range(a..b))
 }
\ No newline at end of file